@@ -0,0 +1,208 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Error, Result};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use walkdir::WalkDir;
+
+use crate::YAFO_FILE_EXTENSION;
+
+/// Decides which files are processed.
+#[derive(Debug)]
+pub struct Filter {
+    forward: bool,
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+}
+
+impl Filter {
+    /// Creates a filter for encryption (`forward`) or decryption. If
+    /// `include` is empty, all files are included.
+    pub fn new(forward: bool, include: &[String], exclude: &[String]) -> Result<Self> {
+        let include = if include.is_empty() {
+            None
+        } else {
+            Some(build_glob_set(include)?)
+        };
+        Ok(Self {
+            forward,
+            include,
+            exclude: build_glob_set(exclude)?,
+        })
+    }
+
+    /// Returns `true` if the file should be processed.
+    ///
+    /// Files that are already encrypted are skipped when encrypting, and
+    /// the other way around when decrypting.
+    pub fn matches(&self, path: &Path) -> bool {
+        is_encrypted(path) != self.forward && self.selects(path)
+    }
+
+    /// Checks a file that is given on its own, failing with the reason if
+    /// it should not be processed.
+    ///
+    /// Unlike [`Filter::matches`], a file without the extension can be
+    /// decrypted, since `--output` may have given it any name.
+    pub fn check(&self, path: &Path) -> Result<()> {
+        if self.forward && is_encrypted(path) {
+            bail!("{} is already encrypted", path.display());
+        }
+        if !self.selects(path) {
+            bail!("{} is excluded by --include or --exclude", path.display());
+        }
+        Ok(())
+    }
+
+    /// Returns `true` if the file is selected by the glob patterns.
+    fn selects(&self, path: &Path) -> bool {
+        if let Some(include) = &self.include {
+            if !include.is_match(path) {
+                return false;
+            }
+        }
+        !self.exclude.is_match(path)
+    }
+}
+
+/// Returns `true` if the file has the extension of encrypted files.
+fn is_encrypted(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.ends_with(YAFO_FILE_EXTENSION))
+}
+
+fn build_glob_set(patterns: &[String]) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(Glob::new(pattern)?);
+    }
+    Ok(builder.build()?)
+}
+
+/// Expands the inputs into the files to be processed.
+///
+/// Directories are traversed if `recursive` is set. Inputs that cannot be
+/// expanded are returned as failures, instead of failing all of them.
+///
+/// A file given more than once (e.g. both `dir` and `dir/file`) is only
+/// returned the first time, since processing it twice concurrently would
+/// corrupt it.
+pub fn collect_files(
+    inputs: &[String],
+    recursive: bool,
+    filter: &Filter,
+) -> (Vec<PathBuf>, Vec<(PathBuf, Error)>) {
+    let mut files = vec![];
+    let mut failures = vec![];
+    let mut seen = BTreeSet::new();
+    let mut push = |path: PathBuf, failures: &mut Vec<_>| match fs::canonicalize(&path) {
+        Ok(canonical) => {
+            if seen.insert(canonical) {
+                files.push(path);
+            }
+        }
+        Err(err) => failures.push((path, err.into())),
+    };
+    for input in inputs {
+        let path = PathBuf::from(input);
+        if path.is_file() {
+            if filter.matches(&path) {
+                push(path, &mut failures);
+            }
+        } else if !path.is_dir() {
+            failures.push((path, anyhow!("File not found")));
+        } else if !recursive {
+            failures.push((path, anyhow!("Is a directory (use --recursive)")));
+        } else {
+            for entry in WalkDir::new(&path).sort_by_file_name() {
+                match entry {
+                    Ok(entry) if entry.file_type().is_file() => {
+                        if filter.matches(entry.path()) {
+                            push(entry.into_path(), &mut failures);
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(err) => {
+                        let path = err.path().unwrap_or(&path).to_owned();
+                        failures.push((path, err.into()));
+                    }
+                }
+            }
+        }
+    }
+    (files, failures)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    use super::{collect_files, Filter};
+
+    #[test]
+    fn filter() {
+        let filter = Filter::new(true, &[], &[]).unwrap();
+        assert!(filter.matches(Path::new("a.txt")));
+        assert!(!filter.matches(Path::new("a.txt.yafo")));
+
+        let filter = Filter::new(false, &[], &[]).unwrap();
+        assert!(!filter.matches(Path::new("a.txt")));
+        assert!(filter.matches(Path::new("dir/a.txt.yafo")));
+
+        let include = ["*.txt".to_owned(), "*.md".to_owned()];
+        let exclude = ["**/secret/**".to_owned()];
+        let filter = Filter::new(true, &include, &exclude).unwrap();
+        assert!(filter.matches(Path::new("dir/a.txt")));
+        assert!(filter.matches(Path::new("README.md")));
+        assert!(!filter.matches(Path::new("dir/a.rs")));
+        assert!(!filter.matches(Path::new("dir/secret/a.txt")));
+
+        assert!(Filter::new(true, &["a[".to_owned()], &[]).is_err());
+    }
+
+    #[test]
+    fn check() {
+        let filter = Filter::new(true, &[], &[]).unwrap();
+        assert!(filter.check(Path::new("a.txt")).is_ok());
+        assert!(filter.check(Path::new("a.txt.yafo")).is_err());
+
+        let filter = Filter::new(false, &[], &[]).unwrap();
+        assert!(filter.check(Path::new("a.txt.yafo")).is_ok());
+        assert!(filter.check(Path::new("a.txt")).is_ok());
+
+        let filter = Filter::new(false, &["*.txt.yafo".to_owned()], &[]).unwrap();
+        assert!(filter.check(Path::new("a.txt.yafo")).is_ok());
+        assert!(filter.check(Path::new("a.md.yafo")).is_err());
+        let filter = Filter::new(true, &[], &["*.tmp".to_owned()]).unwrap();
+        assert!(filter.check(Path::new("a.tmp")).is_err());
+    }
+
+    #[test]
+    fn collect() {
+        let dir = std::env::temp_dir().join(format!("yafo-test-{}-collect", std::process::id()));
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        for name in ["a", "b.yafo", "sub/c"] {
+            fs::write(dir.join(name), name).unwrap();
+        }
+        let input = |name: &str| dir.join(name).to_str().unwrap().to_owned();
+        let filter = Filter::new(true, &[], &[]).unwrap();
+
+        // Files given more than once are only collected the first time.
+        let inputs = [input("sub/c"), input("."), input("./a")];
+        let (files, failures) = collect_files(&inputs, true, &filter);
+        assert!(failures.is_empty());
+        let expected: Vec<PathBuf> = vec![dir.join("sub/c"), dir.join("./a")];
+        assert_eq!(files, expected);
+
+        let inputs = [input("a"), input("sub"), input("missing")];
+        let (files, failures) = collect_files(&inputs, false, &filter);
+        assert_eq!(files, [dir.join("a")]);
+        let failed: Vec<_> = failures.iter().map(|(path, _)| path.clone()).collect();
+        assert_eq!(failed, [dir.join("sub"), dir.join("missing")]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
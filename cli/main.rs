@@ -1,13 +1,27 @@
+mod inputs;
 mod reporter;
+mod secret;
 
-use std::path::Path;
+use std::fs::{self, File};
+use std::io::{self, Cursor, Read};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
 
-use anyhow::Result;
-use clap::{arg, Parser, Subcommand};
+use anyhow::{bail, Context, Result};
+use clap::builder::{PossibleValuesParser, TypedValueParser};
+use clap::{Parser, Subcommand};
+use indicatif::{MultiProgress, ProgressDrawTarget};
+use yafo::header::HEADER_LEN;
+use yafo::key_init::DEFAULT_PBKDF2_ITERATIONS;
 use yafo::pipeline::ProgressReporter;
-use yafo::{Cipher, DecryptState, EncryptState, KeyInit, Pipeline};
+use yafo::types::DataChunk;
+use yafo::{Algorithm, Cipher, Header, KeyDerivation, Pipeline};
 
+use inputs::Filter;
 use reporter::Reporter;
+use secret::{KeyArgs, Secret};
 
 #[derive(Debug, Parser)]
 #[command(version, about = "Yet Another File Obfuscator")]
@@ -18,82 +32,623 @@ pub struct Cli {
 
 #[derive(Debug, Subcommand)]
 pub enum Commands {
-    #[command(alias = "e", about = "Encrypt the file")]
+    #[command(alias = "e", about = "Encrypt the files")]
     Encrypt(Payload),
-    #[command(alias = "d", about = "Decrypt the file")]
+    #[command(alias = "d", about = "Decrypt the files")]
     Decrypt(Payload),
+    #[command(
+        alias = "c",
+        about = "Check whether the key matches the encrypted file"
+    )]
+    Check(CheckPayload),
 }
 
 #[derive(Debug, Clone, Parser)]
 pub struct Payload {
-    #[arg(short, long, help = "The mnemonic phrase to derive the key")]
-    pub key: String,
+    #[command(flatten)]
+    pub key: KeyArgs,
     #[arg(short, long, default_value = "false", help = "Run silently")]
     pub silent: bool,
-    #[arg(help = "The file to be encrypted or decrypted")]
+    #[arg(
+        long,
+        default_value = "false",
+        help = "Process the file without a container header"
+    )]
+    pub raw: bool,
+    #[arg(
+        long,
+        default_value_t = DEFAULT_PBKDF2_ITERATIONS,
+        value_parser = clap::value_parser!(u32).range(1..),
+        help = "The iteration count of the key derivation when encrypting"
+    )]
+    pub kdf_iterations: u32,
+    #[arg(
+        long,
+        default_value = "4M",
+        value_parser = parse_size,
+        help = "The capacity of the I/O buffers in bytes (suffixes K, M and G are supported)"
+    )]
+    pub buffer_size: usize,
+    #[arg(
+        long,
+        default_value = "false",
+        help = "Append an integrity tag when encrypting, or check it when decrypting a raw file"
+    )]
+    pub tag: bool,
+    #[arg(
+        short,
+        long,
+        value_parser = algorithm_parser(),
+        help = "The algorithm to encrypt with, or to decrypt a raw file with [default: v1]"
+    )]
+    pub algorithm: Option<Algorithm>,
+    #[cfg(feature = "strong")]
+    #[arg(
+        long,
+        default_value = "false",
+        conflicts_with_all = ["raw", "algorithm"],
+        help = "Encrypt with ChaCha20 instead of the fast obfuscation algorithm"
+    )]
+    pub strong: bool,
+    #[arg(
+        short,
+        long,
+        help = "Write the result to the given path (`-` for stdout) and leave the input untouched"
+    )]
+    pub output: Option<String>,
+    #[arg(
+        long,
+        default_value = "false",
+        conflicts_with = "output",
+        help = "Write the result to stdout, same as `--output -`"
+    )]
+    pub stdout: bool,
+    #[arg(
+        short,
+        long,
+        default_value = "false",
+        help = "Process the files in the given directories recursively"
+    )]
+    pub recursive: bool,
+    #[arg(
+        long,
+        value_name = "GLOB",
+        help = "Only process the files whose paths match the pattern (can be repeated)"
+    )]
+    pub include: Vec<String>,
+    #[arg(
+        long,
+        value_name = "GLOB",
+        help = "Skip the files whose paths match the pattern (can be repeated)"
+    )]
+    pub exclude: Vec<String>,
+    #[arg(
+        short,
+        long,
+        default_value_t = 1,
+        value_parser = clap::value_parser!(u32).range(1..).map(|jobs| jobs as usize),
+        help = "The number of files to process concurrently"
+    )]
+    pub jobs: usize,
+    #[arg(
+        required = true,
+        help = "The files or directories to be encrypted or decrypted (`-` for stdin, which implies --stdout)"
+    )]
+    pub inputs: Vec<String>,
+}
+
+#[derive(Debug, Clone, Parser)]
+pub struct CheckPayload {
+    #[command(flatten)]
+    pub key: KeyArgs,
+    #[arg(help = "The encrypted file to be checked (`-` for stdin)")]
     pub input: String,
 }
 
-const YAFO_FILE_EXTENSION: &str = ".yafo";
+impl Payload {
+    /// Returns the algorithm given by the options.
+    fn algorithm(&self) -> Algorithm {
+        #[cfg(feature = "strong")]
+        if self.strong {
+            return Algorithm::ChaCha20;
+        }
+        self.algorithm.unwrap_or_default()
+    }
+}
+
+pub(crate) const YAFO_FILE_EXTENSION: &str = ".yafo";
+
+/// Path standing for stdin as the input, or stdout as the output.
+const STDIO_PATH: &str = "-";
+
+/// Where the data is read from.
+#[derive(Debug)]
+enum Source<'a> {
+    File(&'a Path),
+    /// The standard input, after the given bytes that have already been
+    /// consumed from it (e.g. to read the container header).
+    Stdin(Vec<u8>),
+}
+
+/// Where the result is written to.
+#[derive(Debug, Clone, Copy)]
+enum Destination<'a> {
+    /// Replaces the content of the source file.
+    InPlace,
+    File(&'a Path),
+    Stdout,
+}
+
+/// Parses a size in bytes with an optional binary unit suffix.
+fn parse_size(arg: &str) -> Result<usize, String> {
+    let (digits, shift) = match arg.chars().last().map(|c| c.to_ascii_uppercase()) {
+        Some('K') => (&arg[..arg.len() - 1], 10),
+        Some('M') => (&arg[..arg.len() - 1], 20),
+        Some('G') => (&arg[..arg.len() - 1], 30),
+        _ => (arg, 0),
+    };
+    let value: usize = digits
+        .parse()
+        .map_err(|_| format!("invalid size `{}`", arg))?;
+    value
+        .checked_mul(1 << shift)
+        .ok_or_else(|| format!("size `{}` is too large", arg))
+}
+
+/// Accepts the names of the algorithms available in this build.
+fn algorithm_parser() -> impl TypedValueParser<Value = Algorithm> {
+    PossibleValuesParser::new(Algorithm::ALL.iter().map(Algorithm::name))
+        .map(|name| name.parse().expect("the name should be valid"))
+}
+
+/// Creates the cipher of the given algorithm, keyed by `key`, or by the
+/// key derived from the phrase by `kdf` if the algorithm needs it.
+fn new_cipher(
+    algorithm: Algorithm,
+    secret: &Secret,
+    kdf: &KeyDerivation,
+    key: DataChunk,
+    forward: bool,
+) -> Result<Box<dyn Cipher + Send>> {
+    let cipher = match secret {
+        Secret::Key(_) if algorithm.needs_seed_phrase() => {
+            bail!(
+                "{} needs a phrase, it can't be used with a raw key",
+                algorithm
+            )
+        }
+        Secret::Phrase(phrase) if algorithm.needs_seed_phrase() => {
+            if forward {
+                algorithm.encryptor_with_derived_key(phrase, kdf)
+            } else {
+                algorithm.decryptor_with_derived_key(phrase, kdf)
+            }
+        }
+        _ if forward => algorithm.encryptor(key),
+        _ => algorithm.decryptor(key),
+    };
+    cipher.with_context(|| format!("Failed to create the {} cipher", algorithm))
+}
+
+/// Fails if the algorithm is unsafe to use without the container header.
+fn check_raw_algorithm(algorithm: Algorithm) -> Result<()> {
+    match algorithm {
+        #[cfg(feature = "strong")]
+        Algorithm::ChaCha20 => bail!(
+            "{} requires the container header, it can't be used with --raw",
+            algorithm
+        ),
+        _ => Ok(()),
+    }
+}
 
 fn run_pipeline<R, C>(
     pipeline: Pipeline<R>,
-    path: &Path,
+    source: Source,
+    destination: Destination,
     cipher: C,
-    forward: bool,
-    silent: bool,
+    reporter: Option<Reporter>,
 ) -> Result<()>
 where
     R: ProgressReporter,
     C: Cipher,
 {
-    if silent {
-        pipeline.process_file(path, cipher)?
-    } else {
-        pipeline
-            .with_progress_reporter(Reporter::new(forward))
-            .process_file(path, cipher)?
+    fn run<R: ProgressReporter, C: Cipher>(
+        pipeline: Pipeline<R>,
+        source: Source,
+        destination: Destination,
+        cipher: C,
+    ) -> yafo::Result<()> {
+        let (input, total_len): (Box<dyn Read>, _) = match (source, destination) {
+            (Source::File(path), Destination::InPlace) => {
+                return pipeline.process_file(path, cipher)
+            }
+            (Source::File(path), Destination::File(output)) => {
+                return pipeline.process_file_to(path, output, cipher)
+            }
+            (Source::File(path), Destination::Stdout) => {
+                let file = File::open(path)?;
+                let len = file.metadata()?.len();
+                (Box::new(file), Some(len))
+            }
+            (Source::Stdin(consumed), _) => (
+                Box::new(Cursor::new(consumed).chain(io::stdin().lock())),
+                None,
+            ),
+        };
+
+        match destination {
+            Destination::File(output) => pipeline.process_to_file(input, output, cipher, total_len),
+            _ => pipeline.process(input, io::stdout().lock(), cipher, total_len),
+        }
+    }
+
+    match reporter {
+        Some(reporter) => run(
+            pipeline.with_progress_reporter(reporter),
+            source,
+            destination,
+            cipher,
+        )?,
+        None => run(pipeline, source, destination, cipher)?,
     }
 
     Ok(())
 }
 
+/// Reads the container header of the file and derives the key from the
+/// secret, failing if the file cannot be decrypted with it.
+fn derive_key_for_file(path: &Path, secret: &Secret) -> Result<(Header, DataChunk)> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    derive_key_for_input(file, &path.display().to_string(), secret)
+}
+
+/// Reads the container header from the given input and derives the key
+/// from the secret, failing if the input cannot be decrypted with it.
+fn derive_key_for_input<R: Read>(
+    input: R,
+    name: &str,
+    secret: &Secret,
+) -> Result<(Header, DataChunk)> {
+    let header = match Header::read_from(input) {
+        Ok(header) => header,
+        Err(yafo::Error::Io(err)) => {
+            return Err(err).with_context(|| format!("Failed to read {}", name))
+        }
+        Err(err) => bail!(
+            "{} is not a supported yafo file: {} (use --raw for files without a header)",
+            name,
+            err
+        ),
+    };
+
+    let key = secret.derive_key(&header.kdf);
+    if !header.matches_key(&key) {
+        bail!("Wrong key for {}", name);
+    }
+
+    Ok((header, key))
+}
+
 fn main() -> Result<()> {
     let args = Cli::parse();
     let (forward, payload) = match args.command {
         Commands::Encrypt(payload) => (true, payload),
         Commands::Decrypt(payload) => (false, payload),
+        Commands::Check(payload) => {
+            let secret = Secret::read(&payload.key, false)?;
+            if payload.input == STDIO_PATH {
+                derive_key_for_input(io::stdin().lock(), "stdin", &secret)?;
+                println!("The key matches stdin.");
+            } else {
+                let path = Path::new(&payload.input);
+                derive_key_for_file(path, &secret)?;
+                println!("The key matches {}.", path.display());
+            }
+            return Ok(());
+        }
+    };
+
+    let secret = Secret::read(&payload.key, forward)?;
+    let destination = match payload.output.as_deref() {
+        _ if payload.stdout => Some(Destination::Stdout),
+        Some(STDIO_PATH) => Some(Destination::Stdout),
+        Some(output) => Some(Destination::File(Path::new(output))),
+        None => None,
     };
-    // Check if file exists.
-    let path = Path::new(&payload.input);
-    if !path.exists() {
-        eprintln!("File not found: {}", path.display());
-        std::process::exit(1);
+
+    // A single input is processed as is, and errors are reported directly.
+    if let [input] = payload.inputs.as_slice() {
+        if input == STDIO_PATH {
+            let destination = destination.unwrap_or(Destination::Stdout);
+            return process_input(
+                &payload,
+                &secret,
+                Source::Stdin(vec![]),
+                destination,
+                forward,
+                None,
+            );
+        }
+        let path = Path::new(input);
+        if !path.exists() {
+            eprintln!("File not found: {}", path.display());
+            std::process::exit(1);
+        }
+        if path.is_file() {
+            Filter::new(forward, &payload.include, &payload.exclude)?.check(path)?;
+            let destination = destination.unwrap_or(Destination::InPlace);
+            return process_input(
+                &payload,
+                &secret,
+                Source::File(path),
+                destination,
+                forward,
+                None,
+            );
+        }
+    }
+
+    if payload.inputs.iter().any(|input| input == STDIO_PATH) {
+        bail!("stdin can't be processed with other inputs");
+    }
+    if destination.is_some() {
+        bail!("--output and --stdout can only be used with a single input file");
+    }
+
+    let (succeeded, failures) = process_batch(&payload, &secret, forward)?;
+    if !payload.silent {
+        println!(
+            "{} {} file(s), {} failed.",
+            if forward { "Encrypted" } else { "Decrypted" },
+            succeeded,
+            failures.len()
+        );
+    }
+    if failures.is_empty() {
+        return Ok(());
     }
+    for (path, err) in &failures {
+        eprintln!("Failed to process {}: {:#}", path.display(), err);
+    }
+    std::process::exit(1);
+}
+
+/// Processes the files of the inputs in place with `payload.jobs`
+/// workers, returning the number of processed files and the failures.
+fn process_batch(
+    payload: &Payload,
+    secret: &Secret,
+    forward: bool,
+) -> Result<(usize, Vec<(PathBuf, anyhow::Error)>)> {
+    let filter = Filter::new(forward, &payload.include, &payload.exclude)?;
+    let (files, failures) = inputs::collect_files(&payload.inputs, payload.recursive, &filter);
+    let total = files.len();
+    let queue = Mutex::new(files.into_iter());
+    let succeeded = AtomicUsize::new(0);
+    let failures = Mutex::new(failures);
 
-    let pipeline = Pipeline::new().with_buffer();
-    let key = payload.key.as_str();
-    let silent = payload.silent;
+    let multi = MultiProgress::new();
+    if payload.silent {
+        multi.set_draw_target(ProgressDrawTarget::hidden());
+    }
+    let overall = multi.add(reporter::overall_bar(total as u64));
+    thread::scope(|scope| {
+        for _ in 0..payload.jobs.min(total) {
+            scope.spawn(|| loop {
+                let Some(path) = queue.lock().unwrap().next() else {
+                    break;
+                };
+                let source = Source::File(&path);
+                match process_input(
+                    payload,
+                    secret,
+                    source,
+                    Destination::InPlace,
+                    forward,
+                    Some(&multi),
+                ) {
+                    Ok(()) => {
+                        succeeded.fetch_add(1, Ordering::Relaxed);
+                    }
+                    Err(err) => failures.lock().unwrap().push((path, err)),
+                }
+                overall.inc(1);
+            });
+        }
+    });
+    overall.finish_and_clear();
+
+    let succeeded = succeeded.into_inner();
+    let mut failures = failures.into_inner().unwrap();
+    // Workers finish in any order.
+    failures.sort_by(|(a, _), (b, _)| a.cmp(b));
+    Ok((succeeded, failures))
+}
+
+/// Encrypts or decrypts a single input, showing the progress in `multi`
+/// if given.
+fn process_input(
+    payload: &Payload,
+    secret: &Secret,
+    mut source: Source,
+    destination: Destination,
+    forward: bool,
+    multi: Option<&MultiProgress>,
+) -> Result<()> {
+    let name = match &source {
+        Source::File(path) => path.display().to_string(),
+        Source::Stdin(_) => "stdin".to_owned(),
+    };
+    let reporter = (!payload.silent).then(|| Reporter::new(forward, &name, multi));
+    let mut pipeline = Pipeline::new().with_buffer_capacity(payload.buffer_size);
 
     if forward {
-        let encrypt = EncryptState::with_seed_phrase(key);
-        run_pipeline(pipeline, path, encrypt, forward, silent)?;
+        let algorithm = payload.algorithm();
+        let (kdf, key) = if payload.raw {
+            check_raw_algorithm(algorithm)?;
+            (KeyDerivation::V1, secret.raw_key())
+        } else {
+            let kdf = KeyDerivation::new_salted_with_iterations(payload.kdf_iterations)?;
+            let key = secret.derive_key(&kdf);
+            let mut header = Header::new(&key, kdf);
+            header.algorithm = algorithm;
+            pipeline = pipeline.with_header(header);
+            (kdf, key)
+        };
+        if payload.tag {
+            pipeline = pipeline.with_tag(&key);
+        }
+
+        let encrypt = new_cipher(algorithm, secret, &kdf, key, forward)?;
+        let path = match source {
+            Source::File(path) => Some(path),
+            Source::Stdin(_) => None,
+        };
+        run_pipeline(pipeline, source, destination, encrypt, reporter)?;
+        let (Some(path), Destination::InPlace) = (path, destination) else {
+            return Ok(());
+        };
 
         // Rename the file and add the extension ".yafo" to it.
-        let mut new_path = payload.input.clone();
-        new_path.push_str(YAFO_FILE_EXTENSION);
-        std::fs::rename(&payload.input, &new_path)?;
+        let mut new_path = path.as_os_str().to_owned();
+        new_path.push(YAFO_FILE_EXTENSION);
+        fs::rename(path, new_path)?;
     } else {
-        let decrypt = DecryptState::with_seed_phrase(key);
-        run_pipeline(pipeline, path, decrypt, forward, silent)?;
+        let decrypt = if payload.raw {
+            let algorithm = payload.algorithm();
+            check_raw_algorithm(algorithm)?;
+            let key = secret.raw_key();
+            if payload.tag {
+                pipeline = pipeline.verify_tag(&key);
+            }
+            new_cipher(algorithm, secret, &KeyDerivation::V1, key, forward)?
+        } else {
+            pipeline = pipeline.strip_header();
+            let (header, key) = match &mut source {
+                Source::File(path) => derive_key_for_file(path, secret)?,
+                Source::Stdin(consumed) => {
+                    // The header is passed to the pipeline again later.
+                    io::stdin()
+                        .lock()
+                        .take(HEADER_LEN as u64)
+                        .read_to_end(consumed)?;
+                    derive_key_for_input(&consumed[..], &name, secret)?
+                }
+            };
+            if let Some(algorithm) = payload.algorithm {
+                if algorithm != header.algorithm {
+                    bail!(
+                        "{} is encrypted with {}, not {}",
+                        name,
+                        header.algorithm,
+                        algorithm
+                    );
+                }
+            }
+            // Tagged containers are always checked.
+            if header.has_tag() {
+                pipeline = pipeline.verify_tag(&key);
+            }
+            new_cipher(header.algorithm, secret, &header.kdf, key, forward)?
+        };
+        let path = match source {
+            Source::File(path) => Some(path),
+            Source::Stdin(_) => None,
+        };
+        run_pipeline(pipeline, source, destination, decrypt, reporter)?;
+        let (Some(path), Destination::InPlace) = (path, destination) else {
+            return Ok(());
+        };
 
         // Check if the file name has the extension of ".yafo".
         // If it does, remove it. Otherwise, do nothing.
-        let file_path = payload.input;
-        if let Some(stripped) = file_path.strip_suffix(YAFO_FILE_EXTENSION) {
-            std::fs::rename(&file_path, &stripped)?;
+        let file_name = path.file_name().and_then(|name| name.to_str());
+        if let Some(stripped) = file_name.and_then(|name| name.strip_suffix(YAFO_FILE_EXTENSION)) {
+            fs::rename(path, path.with_file_name(stripped))?;
         }
-    };
+    }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use clap::Parser;
+
+    use super::{parse_size, process_batch, Cli, Commands, Payload};
+    use crate::secret::Secret;
+
+    #[test]
+    fn size() {
+        assert_eq!(parse_size("4096"), Ok(4096));
+        assert_eq!(parse_size("4k"), Ok(4 << 10));
+        assert_eq!(parse_size("4M"), Ok(4 << 20));
+        assert_eq!(parse_size("1G"), Ok(1 << 30));
+        assert_eq!(parse_size("0"), Ok(0));
+
+        assert!(parse_size("").is_err());
+        assert!(parse_size("K").is_err());
+        assert!(parse_size("-1").is_err());
+        assert!(parse_size("4KB").is_err());
+        assert!(parse_size(&format!("{}G", usize::MAX)).is_err());
+    }
+
+    fn parse_payload(args: &[&str]) -> Payload {
+        let args = ["yafo", "e", "-s", "--kdf-iterations", "16"]
+            .iter()
+            .chain(args);
+        match Cli::parse_from(args).command {
+            Commands::Encrypt(payload) => payload,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn overlapping_inputs() {
+        let dir = std::env::temp_dir().join(format!("yafo-test-{}-batch", std::process::id()));
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        let names = ["a", "b", "sub/c", "sub/d"];
+        for name in names {
+            fs::write(dir.join(name), name.repeat(1000)).unwrap();
+        }
+        let secret = Secret::Phrase("you can not see me".to_owned());
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_owned();
+
+        // Every file is given more than once, and must be processed once.
+        let payload = parse_payload(&[
+            "-j",
+            "4",
+            "-r",
+            &path("."),
+            &path("a"),
+            &path("sub"),
+            &path("sub/../b"),
+        ]);
+        let (succeeded, failures) = process_batch(&payload, &secret, true).unwrap();
+        assert!(failures.is_empty(), "{:?}", failures);
+        assert_eq!(succeeded, names.len());
+
+        let payload = parse_payload(&[
+            "-j",
+            "4",
+            "-r",
+            &path("sub/c.yafo"),
+            &path("."),
+            &path("a.yafo"),
+        ]);
+        let (succeeded, failures) = process_batch(&payload, &secret, false).unwrap();
+        assert!(failures.is_empty(), "{:?}", failures);
+        assert_eq!(succeeded, names.len());
+        for name in names {
+            assert_eq!(
+                fs::read(dir.join(name)).unwrap(),
+                name.repeat(1000).as_bytes()
+            );
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,17 +1,37 @@
 use std::fmt::Write;
 
-use indicatif::{FormattedDuration, ProgressBar, ProgressState, ProgressStyle};
-use yafo::pipeline::ProgressReporter;
+use indicatif::{
+    FormattedDuration, HumanBytes, MultiProgress, ProgressBar, ProgressState, ProgressStyle,
+};
+use yafo::pipeline::{ProgressReporter, Summary};
+use yafo::Error;
+
+/// Creates the bar showing how many files of a batch are processed.
+pub fn overall_bar(total: u64) -> ProgressBar {
+    let pb = ProgressBar::new(total);
+    pb.set_style(
+        ProgressStyle::with_template("{prefix:.yellow} [{bar:40.white}] {pos}/{len} ({elapsed})")
+            .unwrap()
+            .progress_chars("=> "),
+    );
+    pb.set_prefix("Files");
+    pb
+}
 
 #[derive(Debug)]
 pub struct Reporter {
     progress_bar: ProgressBar,
-    processed_size: usize,
+    /// Whether the bar is shown with other bars, where the messages of
+    /// every file are left out.
+    in_batch: bool,
 }
 
 impl Reporter {
-    pub fn new(forward: bool) -> Self {
-        let pb = ProgressBar::new(0);
+    pub fn new(forward: bool, name: &str, multi: Option<&MultiProgress>) -> Self {
+        let pb = match multi {
+            Some(multi) => multi.add(ProgressBar::new(0)),
+            None => ProgressBar::new(0),
+        };
         pb.set_style(
             ProgressStyle::with_template(
                 "{spinner:.green} {prefix:.yellow} [{bar:40.white}] {bytes}/{total_bytes} ({eta_prompt:.blue} {eta_precise:.blue})",
@@ -23,31 +43,63 @@ impl Reporter {
             .tick_chars("⠈⠐⠠⢀⡀⠄⠂⠁⠈")
             .progress_chars("=> "),
         );
-        pb.set_prefix(if forward { "Encrypting" } else { "Decrypting" });
+        let action = if forward { "Encrypting" } else { "Decrypting" };
+        pb.set_prefix(format!("{} {}", action, name));
         Self {
             progress_bar: pb,
-            processed_size: 0,
+            in_batch: multi.is_some(),
         }
     }
 }
 
 impl ProgressReporter for Reporter {
-    fn bytes_processed(&mut self, n: usize, total: Option<usize>) {
-        let Some(total) = total else {
-            return;
-        };
-        self.processed_size += n;
+    fn started(&mut self, total: Option<u64>) {
+        match total {
+            Some(total) => self.progress_bar.set_length(total),
+            // The length of a stream is unknown, so there is no bar.
+            None => self.progress_bar.set_style(
+                ProgressStyle::with_template(
+                    "{spinner:.green} {prefix:.yellow} {bytes} ({bytes_per_sec})",
+                )
+                .unwrap()
+                .tick_chars("⠈⠐⠠⢀⡀⠄⠂⠁⠈"),
+            ),
+        }
+        self.progress_bar.reset_elapsed();
+    }
 
-        let pb = &self.progress_bar;
-        pb.set_length(total as u64);
-        pb.set_position(self.processed_size as u64);
+    fn bytes_processed(&mut self, n: u64) {
+        self.progress_bar.inc(n);
     }
-}
 
-impl Drop for Reporter {
-    fn drop(&mut self) {
+    fn finished(&mut self, summary: &Summary) {
         self.progress_bar.finish_and_clear();
-        let ela = self.progress_bar.elapsed();
-        println!("\u{2728} Done in {}.", FormattedDuration(ela));
+        if self.in_batch {
+            return;
+        }
+        // Printed to stderr like the progress bar, since the result may be
+        // written to stdout.
+        eprintln!(
+            "\u{2728} Done in {} ({}/s).",
+            FormattedDuration(summary.elapsed),
+            HumanBytes(summary.throughput() as u64)
+        );
+    }
+
+    fn failed(&mut self, _error: &Error, _summary: &Summary) {
+        // The error itself is printed by the caller.
+        if self.in_batch {
+            self.progress_bar.finish_and_clear();
+        } else {
+            self.progress_bar.abandon();
+        }
+    }
+
+    fn cancelled(&mut self, summary: &Summary) {
+        self.progress_bar.abandon();
+        eprintln!(
+            "Cancelled after processing {}.",
+            HumanBytes(summary.bytes_processed)
+        );
     }
 }
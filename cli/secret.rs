@@ -0,0 +1,231 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{bail, Context, Result};
+use clap::Args;
+use yafo::types::{DataChunk, CHUNK_SIZE};
+use yafo::{KeyDerivation, KeyInit};
+
+#[derive(Debug, Clone, Args)]
+#[group(multiple = false)]
+pub struct KeyArgs {
+    #[arg(
+        short,
+        long,
+        help = "The mnemonic phrase to derive the key (visible in the shell history, prefer the other options)"
+    )]
+    pub key: Option<String>,
+    #[arg(
+        long,
+        value_name = "VAR",
+        help = "Read the mnemonic phrase from the given environment variable"
+    )]
+    pub key_env: Option<String>,
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Read the mnemonic phrase from the given file"
+    )]
+    pub key_file: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "KEY",
+        help = "Use the given raw key of 8 bytes, encoded as 16 hex digits or in base64"
+    )]
+    pub raw_key: Option<String>,
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Read the raw key from the given file, which contains the 8 bytes of the key as is"
+    )]
+    pub raw_key_file: Option<PathBuf>,
+}
+
+/// The secret given by the user.
+///
+/// If no key option is given, the phrase is prompted without echo.
+#[derive(Debug)]
+pub enum Secret {
+    /// A mnemonic phrase, from which the key is derived.
+    Phrase(String),
+    /// The initial key itself, which is used as is.
+    Key(DataChunk),
+}
+
+impl Secret {
+    /// Reads the secret given by the arguments, prompting for it if no
+    /// key option is given. The prompted phrase is asked twice if
+    /// `confirm` is set.
+    pub fn read(args: &KeyArgs, confirm: bool) -> Result<Self> {
+        if let Some(raw_key) = &args.raw_key {
+            let key = DataChunk::with_raw_key(raw_key).context("Invalid raw key")?;
+            return Ok(Self::Key(key));
+        }
+        if let Some(path) = &args.raw_key_file {
+            let bytes = fs::read(path)
+                .with_context(|| format!("Failed to read the key from {}", path.display()))?;
+            let Ok(key) = <[u8; CHUNK_SIZE]>::try_from(bytes) else {
+                bail!(
+                    "{} doesn't contain a raw key of {} bytes",
+                    path.display(),
+                    CHUNK_SIZE
+                );
+            };
+            return Ok(Self::Key(DataChunk::from(key)));
+        }
+
+        let phrase = if let Some(key) = &args.key {
+            key.clone()
+        } else if let Some(var) = &args.key_env {
+            env::var(var).with_context(|| format!("Failed to read the key from ${}", var))?
+        } else if let Some(path) = &args.key_file {
+            let bytes = fs::read(path)
+                .with_context(|| format!("Failed to read the key from {}", path.display()))?;
+            let Ok(phrase) = String::from_utf8(bytes) else {
+                bail!(
+                    "{} doesn't contain a UTF-8 phrase (use --raw-key-file for a raw key)",
+                    path.display()
+                );
+            };
+            // Editors usually end the file with a newline.
+            phrase.trim_end_matches(['\r', '\n']).to_owned()
+        } else {
+            prompt(confirm)?
+        };
+
+        if phrase.is_empty() {
+            bail!("The key must not be empty");
+        }
+        Ok(Self::Phrase(phrase))
+    }
+
+    /// Returns the initial key derived by `kdf` from the phrase or the
+    /// raw key.
+    pub fn derive_key(&self, kdf: &KeyDerivation) -> DataChunk {
+        match self {
+            Self::Phrase(phrase) => DataChunk::with_derived_key(phrase, kdf),
+            Self::Key(key) => kdf.derive_key_from_raw(*key),
+        }
+    }
+
+    /// Returns the initial key for files without a header, which derives
+    /// it from a phrase like [`KeyInit::with_seed_phrase`], and uses a raw
+    /// key as is.
+    pub fn raw_key(&self) -> DataChunk {
+        self.derive_key(&KeyDerivation::V1)
+    }
+}
+
+fn prompt(confirm: bool) -> Result<String> {
+    let phrase = rpassword::prompt_password("Key: ").context("Failed to read the key")?;
+    if confirm {
+        let confirmed =
+            rpassword::prompt_password("Confirm key: ").context("Failed to read the key")?;
+        if confirmed != phrase {
+            bail!("The keys don't match");
+        }
+    }
+    Ok(phrase)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+
+    use yafo::types::DataChunk;
+    use yafo::KeyDerivation;
+
+    use super::{KeyArgs, Secret};
+
+    /// Reads the secret from the arguments set by `set`.
+    fn read(set: impl FnOnce(&mut KeyArgs)) -> Option<Secret> {
+        let mut args = KeyArgs {
+            key: None,
+            key_env: None,
+            key_file: None,
+            raw_key: None,
+            raw_key_file: None,
+        };
+        set(&mut args);
+        Secret::read(&args, false).ok()
+    }
+
+    fn phrase(secret: Option<Secret>) -> Option<String> {
+        match secret {
+            Some(Secret::Phrase(phrase)) => Some(phrase),
+            _ => None,
+        }
+    }
+
+    fn raw_key(secret: Option<Secret>) -> Option<DataChunk> {
+        match secret {
+            Some(Secret::Key(key)) => Some(key),
+            _ => None,
+        }
+    }
+
+    fn temp_file(name: &str, content: &[u8]) -> PathBuf {
+        let path = env::temp_dir().join(format!("yafo-test-{}-{}", std::process::id(), name));
+        fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn phrase_options() {
+        let secret = read(|args| args.key = Some("you can not see me".to_owned()));
+        assert_eq!(phrase(secret).as_deref(), Some("you can not see me"));
+        assert!(read(|args| args.key = Some(String::new())).is_none());
+
+        let var = format!("YAFO_TEST_KEY_{}", std::process::id());
+        env::set_var(&var, "from env");
+        let secret = read(|args| args.key_env = Some(var.clone()));
+        assert_eq!(phrase(secret).as_deref(), Some("from env"));
+        env::remove_var(&var);
+        assert!(read(|args| args.key_env = Some(var.clone())).is_none());
+
+        // Trailing newlines are not a part of the phrase.
+        let path = temp_file("key-file", b"from file\r\n");
+        let secret = read(|args| args.key_file = Some(path.clone()));
+        assert_eq!(phrase(secret).as_deref(), Some("from file"));
+
+        // Binary files are never taken as a phrase.
+        fs::write(&path, [0xff, 1, 2, 3, 4, 5, 6, 7]).unwrap();
+        assert!(read(|args| args.key_file = Some(path.clone())).is_none());
+        fs::remove_file(&path).unwrap();
+        assert!(read(|args| args.key_file = Some(path.clone())).is_none());
+    }
+
+    #[test]
+    fn raw_key_options() {
+        let expected = DataChunk::from([0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef]);
+        let secret = read(|args| args.raw_key = Some("0123456789abcdef".to_owned()));
+        assert_eq!(raw_key(secret), Some(expected));
+        assert!(read(|args| args.raw_key = Some("0123456789abcde".to_owned())).is_none());
+
+        // Even a key of UTF-8 bytes is taken as is.
+        let path = temp_file("raw-key-file", b"12345678");
+        let secret = read(|args| args.raw_key_file = Some(path.clone()));
+        assert_eq!(raw_key(secret), Some(DataChunk::from(*b"12345678")));
+
+        fs::write(&path, b"12345678\n").unwrap();
+        assert!(read(|args| args.raw_key_file = Some(path.clone())).is_none());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn raw_key_derivation() {
+        let key = DataChunk::from(*b"12345678");
+        let secret = Secret::Key(key);
+        assert_eq!(secret.raw_key(), key);
+
+        // Every container gets its own key.
+        let kdf = KeyDerivation::new_salted_with_iterations(16).unwrap();
+        let other_kdf = KeyDerivation::new_salted_with_iterations(16).unwrap();
+        assert_ne!(secret.derive_key(&kdf), key);
+        assert_eq!(secret.derive_key(&kdf), secret.derive_key(&kdf));
+        assert_ne!(secret.derive_key(&kdf), secret.derive_key(&other_kdf));
+    }
+}
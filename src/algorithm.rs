@@ -0,0 +1,250 @@
+//! Registry of the cipher algorithms.
+//!
+//! Every algorithm has a stable identifier, which is recorded in the
+//! container [`Header`], and a stable name, which is used by the CLI and
+//! the C APIs. Revisions of an algorithm get new identifiers, so data
+//! encrypted by older revisions can always be decrypted.
+//!
+//! [`Header`]: crate::header::Header
+
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+#[cfg(feature = "strong")]
+use crate::cipher::ChaCha20State;
+use crate::cipher::{
+    Cipher, DecryptState, EncryptState, SeekableDecryptState, SeekableEncryptState,
+};
+use crate::error::{Error, Result};
+use crate::key_init::{KeyDerivation, KeyInit};
+use crate::types::DataChunk;
+
+/// Cipher algorithms that can be selected at runtime.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Hash)]
+#[non_exhaustive]
+pub enum Algorithm {
+    /// The algorithm described in `docs/algorithm-design.md`, implemented
+    /// by [`EncryptState`] and [`DecryptState`].
+    #[default]
+    V1,
+    /// The seekable variant of the algorithm, implemented by
+    /// [`SeekableEncryptState`] and [`SeekableDecryptState`].
+    Seekable,
+    /// The ChaCha20 stream cipher, implemented by [`ChaCha20State`].
+    ///
+    /// It needs the seed phrase and a salted [`KeyDerivation`], so it can
+    /// only be used with the container header.
+    ///
+    /// [`ChaCha20State`]: crate::cipher::ChaCha20State
+    #[cfg(feature = "strong")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "strong")))]
+    ChaCha20,
+}
+
+impl Algorithm {
+    /// Identifier of [`Algorithm::V1`].
+    pub const V1_ID: u8 = 1;
+    /// Identifier of [`Algorithm::Seekable`].
+    pub const SEEKABLE_ID: u8 = 2;
+    /// Identifier of `Algorithm::ChaCha20`, which is reserved even if the
+    /// `strong` feature is disabled.
+    pub const CHACHA20_ID: u8 = 3;
+
+    /// All the algorithms available in this build.
+    pub const ALL: &'static [Algorithm] = &[
+        Self::V1,
+        Self::Seekable,
+        #[cfg(feature = "strong")]
+        Self::ChaCha20,
+    ];
+
+    /// Returns the stable identifier of the algorithm.
+    pub fn id(&self) -> u8 {
+        match self {
+            Self::V1 => Self::V1_ID,
+            Self::Seekable => Self::SEEKABLE_ID,
+            #[cfg(feature = "strong")]
+            Self::ChaCha20 => Self::CHACHA20_ID,
+        }
+    }
+
+    /// Returns the algorithm with the given identifier, or `None` if it's
+    /// unknown or not available in this build.
+    pub fn from_id(id: u8) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .find(|algorithm| algorithm.id() == id)
+            .copied()
+    }
+
+    /// Returns the stable name of the algorithm.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::V1 => "v1",
+            Self::Seekable => "seekable",
+            #[cfg(feature = "strong")]
+            Self::ChaCha20 => "chacha20",
+        }
+    }
+
+    /// Returns whether the cipher derives its own key from the seed
+    /// phrase, instead of using the 64-bit initial key.
+    ///
+    /// Such a cipher can only be created by
+    /// [`Algorithm::encryptor_with_derived_key`] and
+    /// [`Algorithm::decryptor_with_derived_key`].
+    pub fn needs_seed_phrase(&self) -> bool {
+        match self {
+            Self::V1 | Self::Seekable => false,
+            #[cfg(feature = "strong")]
+            Self::ChaCha20 => true,
+        }
+    }
+
+    /// Creates the cipher that encrypts with the given initial key.
+    ///
+    /// Fails with [`Error::InvalidInput`] if the algorithm needs the seed
+    /// phrase, see [`Algorithm::needs_seed_phrase`].
+    pub fn encryptor(&self, key: DataChunk) -> Result<Box<dyn Cipher + Send>> {
+        match self {
+            Self::V1 => Ok(Box::new(EncryptState::with_key(key))),
+            Self::Seekable => Ok(Box::new(SeekableEncryptState::with_key(key))),
+            #[cfg(feature = "strong")]
+            Self::ChaCha20 => Err(Error::InvalidInput(
+                "chacha20 can't be used with an initial key",
+            )),
+        }
+    }
+
+    /// Creates the cipher that decrypts with the given initial key.
+    ///
+    /// Fails with [`Error::InvalidInput`] if the algorithm needs the seed
+    /// phrase, see [`Algorithm::needs_seed_phrase`].
+    pub fn decryptor(&self, key: DataChunk) -> Result<Box<dyn Cipher + Send>> {
+        match self {
+            Self::V1 => Ok(Box::new(DecryptState::with_key(key))),
+            Self::Seekable => Ok(Box::new(SeekableDecryptState::with_key(key))),
+            #[cfg(feature = "strong")]
+            Self::ChaCha20 => Err(Error::InvalidInput(
+                "chacha20 can't be used with an initial key",
+            )),
+        }
+    }
+
+    /// Creates the cipher that encrypts with the key derived from the seed
+    /// phrase by the given derivation.
+    pub fn encryptor_with_derived_key(
+        &self,
+        phrase: &str,
+        derivation: &KeyDerivation,
+    ) -> Result<Box<dyn Cipher + Send>> {
+        match self {
+            #[cfg(feature = "strong")]
+            Self::ChaCha20 => Ok(Box::new(ChaCha20State::with_derived_key(
+                phrase, derivation,
+            )?)),
+            _ => self.encryptor(derivation.derive_key(phrase)),
+        }
+    }
+
+    /// Creates the cipher that decrypts with the key derived from the seed
+    /// phrase by the given derivation.
+    pub fn decryptor_with_derived_key(
+        &self,
+        phrase: &str,
+        derivation: &KeyDerivation,
+    ) -> Result<Box<dyn Cipher + Send>> {
+        match self {
+            #[cfg(feature = "strong")]
+            Self::ChaCha20 => Ok(Box::new(ChaCha20State::with_derived_key(
+                phrase, derivation,
+            )?)),
+            _ => self.decryptor(derivation.derive_key(phrase)),
+        }
+    }
+}
+
+impl Display for Algorithm {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Algorithm {
+    type Err = Error;
+
+    /// Parses the name of the algorithm, ignoring the case.
+    fn from_str(s: &str) -> Result<Self> {
+        Self::ALL
+            .iter()
+            .find(|algorithm| algorithm.name().eq_ignore_ascii_case(s))
+            .copied()
+            .ok_or(Error::InvalidInput("unknown algorithm"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::assert_matches::assert_matches;
+
+    use super::Algorithm;
+    use crate::error::Error;
+    use crate::key_init::KeyDerivation;
+    use crate::test_utils::{payload, SEED_PHRASE};
+    use crate::types::DataChunk;
+
+    #[test]
+    fn lookup() {
+        for &algorithm in Algorithm::ALL {
+            assert_eq!(Algorithm::from_id(algorithm.id()), Some(algorithm));
+            assert_eq!(algorithm.name().parse::<Algorithm>().ok(), Some(algorithm));
+            assert_eq!(algorithm.to_string(), algorithm.name());
+        }
+        assert_eq!(
+            "SEEKABLE".parse::<Algorithm>().ok(),
+            Some(Algorithm::Seekable)
+        );
+        assert_eq!(Algorithm::from_id(0), None);
+        assert_matches!("v0".parse::<Algorithm>(), Err(Error::InvalidInput(_)));
+    }
+
+    #[test]
+    fn round_trip() {
+        let key = DataChunk::from([1, 2, 3, 4, 5, 6, 7, 8]);
+        let plain = payload();
+        for &algorithm in Algorithm::ALL {
+            if algorithm.needs_seed_phrase() {
+                assert_matches!(algorithm.encryptor(key).err(), Some(Error::InvalidInput(_)));
+                assert_matches!(algorithm.decryptor(key).err(), Some(Error::InvalidInput(_)));
+                continue;
+            }
+            let mut data = plain.clone();
+            algorithm.encryptor(key).unwrap().process_buffer(&mut data);
+            assert_ne!(data, plain, "{} doesn't change the data", algorithm);
+            algorithm.decryptor(key).unwrap().process_buffer(&mut data);
+            assert_eq!(data, plain, "{} doesn't round trip", algorithm);
+        }
+    }
+
+    #[test]
+    fn round_trip_with_derived_key() {
+        let kdf = KeyDerivation::Pbkdf2 {
+            salt: [42; 16],
+            iterations: 16,
+        };
+        let plain = payload();
+        for &algorithm in Algorithm::ALL {
+            let mut data = plain.clone();
+            let mut encryptor = algorithm
+                .encryptor_with_derived_key(SEED_PHRASE, &kdf)
+                .unwrap();
+            encryptor.process_buffer(&mut data);
+            assert_ne!(data, plain, "{} doesn't change the data", algorithm);
+            let mut decryptor = algorithm
+                .decryptor_with_derived_key(SEED_PHRASE, &kdf)
+                .unwrap();
+            decryptor.process_buffer(&mut data);
+            assert_eq!(data, plain, "{} doesn't round trip", algorithm);
+        }
+    }
+}
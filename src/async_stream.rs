@@ -0,0 +1,333 @@
+//! Asynchronous streaming adapters over tokio's [`AsyncRead`] and
+//! [`AsyncWrite`].
+//!
+//! These are the asynchronous counterparts of the adapters in the
+//! [`stream`] module, and they produce identical output. See also
+//! [`Pipeline::process_async`] for processing a whole stream.
+//!
+//! ## Example
+//!
+//! ```rust
+//! # use tokio::io::{AsyncReadExt, AsyncWriteExt};
+//! # use yafo::async_stream::{AsyncCipherReader, AsyncCipherWriter};
+//! # use yafo::{DecryptState, EncryptState, KeyInit};
+//! # tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async {
+//! let seed_phrase = "you can not see me";
+//!
+//! let mut writer = AsyncCipherWriter::new(vec![], EncryptState::with_seed_phrase(seed_phrase));
+//! writer.write_all(b"hello").await.unwrap();
+//! writer.shutdown().await.unwrap();
+//! let cipher_text = writer.into_inner();
+//!
+//! let mut reader =
+//!     AsyncCipherReader::new(&cipher_text[..], DecryptState::with_seed_phrase(seed_phrase));
+//! let mut plain_text = String::new();
+//! reader.read_to_string(&mut plain_text).await.unwrap();
+//! assert_eq!(plain_text, "hello");
+//! # });
+//! ```
+//!
+//! [`stream`]: crate::stream
+//! [`Pipeline::process_async`]: crate::pipeline::Pipeline::process_async
+
+use std::io::{Error as IoError, ErrorKind as IoErrorKind, Result};
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::io::chunks::{ChunkAssembler, Step};
+use crate::types::{DataChunk, CHUNK_SIZE};
+use crate::Cipher;
+
+const SCRATCH_SIZE: usize = 4096;
+
+/// An asynchronous reader that processes the bytes read from the inner
+/// reader.
+pub struct AsyncCipherReader<R, C> {
+    inner: R,
+    cipher: C,
+    chunks: ChunkAssembler,
+}
+
+impl<R, C> AsyncCipherReader<R, C> {
+    /// Creates a new reader that processes the bytes from `inner` with
+    /// the given cipher.
+    pub fn new(inner: R, cipher: C) -> Self {
+        Self {
+            inner,
+            cipher,
+            chunks: ChunkAssembler::new(),
+        }
+    }
+
+    /// Gets a reference to the inner reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Unwraps this reader, returning the inner reader.
+    ///
+    /// Bytes that are read but not yet consumed are lost.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R, C> AsyncRead for AsyncCipherReader<R, C>
+where
+    R: AsyncRead + Unpin,
+    C: Cipher + Unpin,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<Result<()>> {
+        let this = self.get_mut();
+        loop {
+            let unfilled = buf.initialize_unfilled();
+            match this.chunks.next_step(unfilled) {
+                Step::Ready(len) => {
+                    buf.advance(len);
+                    return Poll::Ready(Ok(()));
+                }
+                Step::ReadDirect => {
+                    let mut inner_buf = ReadBuf::new(unfilled);
+                    ready!(Pin::new(&mut this.inner).poll_read(cx, &mut inner_buf))?;
+                    let rd_len = inner_buf.filled().len();
+                    if let Some(len) = this.chunks.direct_read(&mut this.cipher, unfilled, rd_len) {
+                        buf.advance(len);
+                        return Poll::Ready(Ok(()));
+                    }
+                }
+                Step::ReadChunk => {
+                    let mut chunk_buf = ReadBuf::new(this.chunks.unfilled());
+                    ready!(Pin::new(&mut this.inner).poll_read(cx, &mut chunk_buf))?;
+                    let rd_len = chunk_buf.filled().len();
+                    this.chunks.chunk_read(&mut this.cipher, rd_len);
+                }
+            }
+        }
+    }
+}
+
+/// An asynchronous writer that processes the bytes before writing them
+/// to the inner writer.
+///
+/// Processed bytes are buffered in the writer until the next write or
+/// flush. Bytes that don't form a whole chunk are kept until more bytes
+/// come, and the last partial chunk is only processed on
+/// [`AsyncWrite::poll_shutdown`]. Unlike [`CipherWriter`], dropping the
+/// writer without shutting it down loses the pending bytes.
+///
+/// [`CipherWriter`]: crate::stream::CipherWriter
+pub struct AsyncCipherWriter<W, C> {
+    inner: W,
+    cipher: C,
+    chunk: DataChunk,
+    filled: usize,
+    /// Processed bytes that are not yet written to the inner writer.
+    output: Vec<u8>,
+    output_pos: usize,
+    tail_written: bool,
+}
+
+impl<W, C> AsyncCipherWriter<W, C> {
+    /// Creates a new writer that processes the bytes with the given cipher
+    /// and writes them to `inner`.
+    pub fn new(inner: W, cipher: C) -> Self {
+        Self {
+            inner,
+            cipher,
+            chunk: DataChunk::default(),
+            filled: 0,
+            output: Vec::with_capacity(SCRATCH_SIZE),
+            output_pos: 0,
+            tail_written: false,
+        }
+    }
+
+    /// Gets a reference to the inner writer.
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Unwraps this writer, returning the inner writer.
+    ///
+    /// Pending bytes are lost unless the writer is shut down.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W, C> AsyncCipherWriter<W, C>
+where
+    W: AsyncWrite + Unpin,
+    C: Cipher + Unpin,
+{
+    fn poll_write_output(&mut self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        while self.output_pos < self.output.len() {
+            let wr_len =
+                ready!(Pin::new(&mut self.inner).poll_write(cx, &self.output[self.output_pos..]))?;
+            if wr_len == 0 {
+                return Poll::Ready(Err(IoError::from(IoErrorKind::WriteZero)));
+            }
+            self.output_pos += wr_len;
+        }
+
+        self.output.clear();
+        self.output_pos = 0;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<W, C> AsyncWrite for AsyncCipherWriter<W, C>
+where
+    W: AsyncWrite + Unpin,
+    C: Cipher + Unpin,
+{
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<Result<usize>> {
+        let this = self.get_mut();
+        if this.tail_written {
+            return Poll::Ready(Err(IoError::other("the writer is shut down")));
+        }
+        ready!(this.poll_write_output(cx))?;
+
+        let mut consumed = 0;
+        if this.filled > 0 {
+            let len = (CHUNK_SIZE - this.filled).min(buf.len());
+            this.chunk.as_mut_slice()[this.filled..this.filled + len].copy_from_slice(&buf[..len]);
+            this.filled += len;
+            consumed += len;
+
+            if this.filled < CHUNK_SIZE {
+                return Poll::Ready(Ok(consumed));
+            }
+            this.filled = 0;
+            this.cipher.process_chunk(&mut this.chunk);
+            this.output.extend_from_slice(this.chunk.as_ref());
+        }
+
+        let len = (buf.len() - consumed).min(SCRATCH_SIZE);
+        let len = len - len % CHUNK_SIZE;
+        let start = this.output.len();
+        this.output
+            .extend_from_slice(&buf[consumed..consumed + len]);
+        this.cipher.process_buffer(&mut this.output[start..]);
+        consumed += len;
+
+        if buf.len() - consumed < CHUNK_SIZE {
+            let len = buf.len() - consumed;
+            this.chunk.as_mut_slice()[..len].copy_from_slice(&buf[consumed..]);
+            this.filled = len;
+            consumed += len;
+        }
+        Poll::Ready(Ok(consumed))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_write_output(cx))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_write_output(cx))?;
+
+        if !this.tail_written {
+            this.tail_written = true;
+            let len = this.filled;
+            this.filled = 0;
+            this.cipher
+                .process_buffer(&mut this.chunk.as_mut_slice()[..len]);
+            this.output.extend_from_slice(&this.chunk.as_ref()[..len]);
+            ready!(this.poll_write_output(cx))?;
+        }
+
+        Pin::new(&mut this.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::{AsyncCipherReader, AsyncCipherWriter};
+    use crate::test_utils::{encrypt_with_pipeline, payload_with_len, SEED_PHRASE};
+    use crate::{DecryptState, EncryptState, KeyInit, Pipeline};
+
+    const PAYLOAD_LEN: usize = 9013;
+    const IO_SIZES: [usize; 6] = [1, 3, 8, 13, 5000, 10000];
+
+    #[tokio::test]
+    async fn reader_matches_pipeline() {
+        let plain = payload_with_len(PAYLOAD_LEN);
+        let expected = encrypt_with_pipeline(plain.as_slice());
+
+        for size in IO_SIZES {
+            let mut reader =
+                AsyncCipherReader::new(&plain[..], EncryptState::with_seed_phrase(SEED_PHRASE));
+            let mut output = vec![];
+            let mut buf = vec![0; size];
+            loop {
+                let rd_len = reader.read(&mut buf).await.expect("failed to read");
+                if rd_len == 0 {
+                    break;
+                }
+                output.extend_from_slice(&buf[..rd_len]);
+            }
+            assert_eq!(output, expected, "mismatched output with size {}", size);
+        }
+    }
+
+    #[tokio::test]
+    async fn writer_matches_pipeline() {
+        let plain = payload_with_len(PAYLOAD_LEN);
+        let expected = encrypt_with_pipeline(plain.as_slice());
+
+        for size in IO_SIZES {
+            let mut writer =
+                AsyncCipherWriter::new(vec![], EncryptState::with_seed_phrase(SEED_PHRASE));
+            for bytes in plain.chunks(size) {
+                writer.write_all(bytes).await.expect("failed to write");
+                writer.flush().await.expect("failed to flush");
+            }
+            writer.shutdown().await.expect("failed to shut down");
+            let output = writer.into_inner();
+            assert_eq!(output, expected, "mismatched output with size {}", size);
+        }
+    }
+
+    #[tokio::test]
+    async fn process_async() {
+        let plain = payload_with_len(PAYLOAD_LEN);
+        let expected = encrypt_with_pipeline(plain.as_slice());
+
+        let mut output = vec![];
+        Pipeline::new()
+            .with_buffer_capacity(100)
+            .process_async(
+                &plain[..],
+                &mut output,
+                EncryptState::with_seed_phrase(SEED_PHRASE),
+                None,
+            )
+            .await
+            .expect("failed to encrypt");
+        assert_eq!(output, expected);
+
+        let mut decrypted = vec![];
+        Pipeline::new()
+            .process_async(
+                &output[..],
+                &mut decrypted,
+                DecryptState::with_seed_phrase(SEED_PHRASE),
+                None,
+            )
+            .await
+            .expect("failed to decrypt");
+        assert_eq!(decrypted, plain);
+    }
+}
@@ -4,6 +4,22 @@
 //! [`EncryptState`] (for encryption) and [`DecryptState`] (for decryption).
 //! They can be used with [`Pipeline`] for any specified purpose.
 //!
+//! [`SeekableEncryptState`] and [`SeekableDecryptState`] implement the
+//! seekable variant of the algorithm, where the key of each chunk only
+//! depends on the initial key and the index of the chunk. They implement
+//! [`SeekableCipher`], so the data can be processed from any offset.
+//!
+//! All the states implement [`ResumableCipher`], so processing can be
+//! paused and continued later from a [`CipherSnapshot`].
+//!
+//! The algorithms above are built for speed, not for security. With the
+//! `strong` feature, [`ChaCha20State`] provides real confidentiality with
+//! the standard ChaCha20 stream cipher. It implements [`SeekableCipher`]
+//! too, but its key is derived from the seed phrase rather than the
+//! initial key, see [`ChaCha20State::with_derived_key`]. So unlike the
+//! other states, it implements neither [`KeyInit`] nor
+//! [`ResumableCipher`].
+//!
 //! To create a cipher, you should use [`KeyInit::with_key`] method of
 //! the types that implement [`KeyInit`] trait.
 //!
@@ -11,12 +27,144 @@
 //! [`KeyInit`]: crate::key_init::KeyInit
 //! [`KeyInit::with_key`]: crate::key_init::KeyInit::with_key
 
-use crate::types::DataChunk;
+use crate::error::Result;
+use crate::key_init::KeyInit;
+use crate::types::{DataChunk, CHUNK_SIZE};
+
+#[cfg(feature = "strong")]
+mod chacha;
+
+#[cfg(feature = "strong")]
+#[cfg_attr(docsrs, doc(cfg(feature = "strong")))]
+pub use chacha::ChaCha20State;
 
 /// Trait implemented by types that provide cipher algorithm.
 pub trait Cipher {
     /// Processes a chunk of data.
     fn process_chunk(&mut self, chunk: &mut DataChunk);
+
+    /// Processes a buffer of data in place.
+    ///
+    /// The buffer is split into chunks the same way as [`Pipeline`] does,
+    /// and the trailing partial chunk (if any) is treated as the tail of
+    /// the data. Thus when processing data across multiple calls, every
+    /// buffer except the last one must have a length that is a multiple
+    /// of [`CHUNK_SIZE`].
+    ///
+    /// [`Pipeline`]: crate::pipeline::Pipeline
+    fn process_buffer(&mut self, data: &mut [u8]) {
+        let mut chunks = data.chunks_exact_mut(CHUNK_SIZE);
+        let mut chunk = DataChunk::default();
+        for bytes in &mut chunks {
+            chunk.as_mut_slice().copy_from_slice(bytes);
+            self.process_chunk(&mut chunk);
+            bytes.copy_from_slice(chunk.as_ref());
+        }
+
+        process_tail(self, chunks.into_remainder());
+    }
+
+    /// Processes a batch of chunks in order.
+    ///
+    /// The result is the same as calling [`Cipher::process_chunk`] for
+    /// each chunk, but implementations may process them more efficiently.
+    fn process_chunks(&mut self, chunks: &mut [DataChunk]) {
+        for chunk in chunks {
+            self.process_chunk(chunk);
+        }
+    }
+
+    /// Returns the number of bytes the cipher can still process, or `None`
+    /// if it's unlimited.
+    ///
+    /// Processing more than that panics. [`Pipeline`] checks it and fails
+    /// with [`Error::InvalidInput`] instead.
+    ///
+    /// [`Pipeline`]: crate::pipeline::Pipeline
+    /// [`Error::InvalidInput`]: crate::error::Error::InvalidInput
+    fn remaining_len(&self) -> Option<u64> {
+        None
+    }
+}
+
+/// Trait implemented by ciphers that can start processing at any chunk.
+///
+/// The output of such a cipher only depends on the initial key and the
+/// position of the data, so seeking to a chunk doesn't require processing
+/// the chunks before it.
+pub trait SeekableCipher: Cipher {
+    /// Returns the index of the next chunk to be processed.
+    fn chunk_index(&self) -> u64;
+
+    /// Sets the index of the next chunk to be processed.
+    ///
+    /// Fails with [`Error::InvalidInput`] if the cipher can't reach the
+    /// chunk.
+    ///
+    /// [`Error::InvalidInput`]: crate::error::Error::InvalidInput
+    fn seek_chunk(&mut self, index: u64) -> Result<()>;
+}
+
+/// Trait implemented by ciphers whose state can be captured and restored.
+///
+/// Restoring a snapshot into a cipher of the same type makes it continue
+/// exactly where the captured cipher was, so a stream can be processed
+/// piece by piece, even in different processes. Note that all pieces
+/// except the last one must consist of whole chunks, since a partial
+/// chunk is always processed as the tail of the stream.
+pub trait ResumableCipher: Cipher {
+    /// Captures the current state of the cipher.
+    fn snapshot(&self) -> CipherSnapshot;
+
+    /// Restores the state captured by [`ResumableCipher::snapshot`].
+    fn restore(&mut self, snapshot: &CipherSnapshot);
+
+    /// Creates a cipher from the state captured by
+    /// [`ResumableCipher::snapshot`].
+    ///
+    /// The snapshot carries the whole state, so the key is not needed.
+    fn from_snapshot(snapshot: &CipherSnapshot) -> Self
+    where
+        Self: KeyInit + Sized,
+    {
+        let mut cipher = Self::with_key(DataChunk::default());
+        cipher.restore(snapshot);
+        cipher
+    }
+}
+
+/// Length of the encoded [`CipherSnapshot`] in bytes.
+pub const SNAPSHOT_LEN: usize = 16;
+
+/// A captured state of a [`ResumableCipher`].
+///
+/// The snapshot can be stored with [`CipherSnapshot::to_bytes`], or with
+/// serde when the `serde` feature is enabled. It contains key material,
+/// keep it as secret as the key.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CipherSnapshot {
+    key: DataChunk,
+    chunk_index: u64,
+}
+
+impl CipherSnapshot {
+    /// Encodes the snapshot into bytes.
+    pub fn to_bytes(&self) -> [u8; SNAPSHOT_LEN] {
+        let mut bytes = [0; SNAPSHOT_LEN];
+        bytes[0..8].copy_from_slice(&self.key.0);
+        bytes[8..16].copy_from_slice(&self.chunk_index.to_le_bytes());
+        bytes
+    }
+
+    /// Decodes the snapshot from bytes.
+    pub fn from_bytes(bytes: &[u8; SNAPSHOT_LEN]) -> Self {
+        let (key, chunk_index) = bytes.split_at(8);
+        Self {
+            key: DataChunk(key.try_into().unwrap()),
+            chunk_index: u64::from_le_bytes(chunk_index.try_into().unwrap()),
+        }
+    }
 }
 
 macro_rules! impl_state {
@@ -34,11 +182,85 @@ macro_rules! impl_state {
                 Self { current_key: key }
             }
         }
+
+        impl ResumableCipher for $name {
+            fn snapshot(&self) -> CipherSnapshot {
+                CipherSnapshot {
+                    key: self.current_key,
+                    chunk_index: 0,
+                }
+            }
+
+            fn restore(&mut self, snapshot: &CipherSnapshot) {
+                self.current_key = snapshot.key;
+            }
+        }
     };
 }
 
 impl_state!(EncryptState, DecryptState);
 
+macro_rules! impl_seekable_state {
+    ($($name:ident => $transform:ident),*) => {
+        $(impl_seekable_state!(@ $name, $transform);)*
+    };
+
+    (@ $name:ident, $transform:ident) => {
+        #[derive(Clone)]
+        pub struct $name {
+            initial_key: u64,
+            chunk_index: u64,
+        }
+
+        impl $crate::key_init::KeyInit for $name {
+            fn with_key(key: DataChunk) -> Self {
+                Self {
+                    initial_key: u64::from_le_bytes(key.0),
+                    chunk_index: 0,
+                }
+            }
+        }
+
+        impl Cipher for $name {
+            fn process_chunk(&mut self, chunk: &mut DataChunk) {
+                let key = chunk_key(self.initial_key, self.chunk_index);
+                chunk.0 = $transform(key, u64::from_le_bytes(chunk.0)).to_le_bytes();
+                self.chunk_index = self.chunk_index.wrapping_add(1);
+            }
+        }
+
+        impl SeekableCipher for $name {
+            fn chunk_index(&self) -> u64 {
+                self.chunk_index
+            }
+
+            fn seek_chunk(&mut self, index: u64) -> Result<()> {
+                self.chunk_index = index;
+                Ok(())
+            }
+        }
+
+        impl ResumableCipher for $name {
+            fn snapshot(&self) -> CipherSnapshot {
+                CipherSnapshot {
+                    key: DataChunk(self.initial_key.to_le_bytes()),
+                    chunk_index: self.chunk_index,
+                }
+            }
+
+            fn restore(&mut self, snapshot: &CipherSnapshot) {
+                self.initial_key = u64::from_le_bytes(snapshot.key.0);
+                self.chunk_index = snapshot.chunk_index;
+            }
+        }
+    };
+}
+
+impl_seekable_state!(
+    SeekableEncryptState => encrypt_with_key,
+    SeekableDecryptState => decrypt_with_key
+);
+
 impl Cipher for EncryptState {
     fn process_chunk(&mut self, chunk: &mut DataChunk) {
         let c_sum = calculate_sum(chunk);
@@ -51,6 +273,17 @@ impl Cipher for EncryptState {
 
         rotate_key(&mut self.current_key, c_sum);
     }
+
+    fn process_buffer(&mut self, data: &mut [u8]) {
+        let mut chunks = data.chunks_exact_mut(CHUNK_SIZE);
+        process_words(&mut self.current_key, as_arrays(&mut chunks), encrypt_word);
+        process_tail(self, chunks.into_remainder());
+    }
+
+    fn process_chunks(&mut self, chunks: &mut [DataChunk]) {
+        let chunks = chunks.iter_mut().map(|chunk| chunk.as_mut_slice());
+        process_words(&mut self.current_key, chunks, encrypt_word);
+    }
 }
 
 impl Cipher for DecryptState {
@@ -65,6 +298,17 @@ impl Cipher for DecryptState {
         let c_sum = calculate_sum(chunk);
         rotate_key(&mut self.current_key, c_sum);
     }
+
+    fn process_buffer(&mut self, data: &mut [u8]) {
+        let mut chunks = data.chunks_exact_mut(CHUNK_SIZE);
+        process_words(&mut self.current_key, as_arrays(&mut chunks), decrypt_word);
+        process_tail(self, chunks.into_remainder());
+    }
+
+    fn process_chunks(&mut self, chunks: &mut [DataChunk]) {
+        let chunks = chunks.iter_mut().map(|chunk| chunk.as_mut_slice());
+        process_words(&mut self.current_key, chunks, decrypt_word);
+    }
 }
 
 impl<T> Cipher for &mut T
@@ -74,6 +318,51 @@ where
     fn process_chunk(&mut self, chunk: &mut DataChunk) {
         (*self).process_chunk(chunk)
     }
+
+    fn process_buffer(&mut self, data: &mut [u8]) {
+        (*self).process_buffer(data)
+    }
+
+    fn process_chunks(&mut self, chunks: &mut [DataChunk]) {
+        (*self).process_chunks(chunks)
+    }
+
+    fn remaining_len(&self) -> Option<u64> {
+        (**self).remaining_len()
+    }
+}
+
+impl<T> Cipher for Box<T>
+where
+    T: Cipher + ?Sized,
+{
+    fn process_chunk(&mut self, chunk: &mut DataChunk) {
+        (**self).process_chunk(chunk)
+    }
+
+    fn process_buffer(&mut self, data: &mut [u8]) {
+        (**self).process_buffer(data)
+    }
+
+    fn process_chunks(&mut self, chunks: &mut [DataChunk]) {
+        (**self).process_chunks(chunks)
+    }
+
+    fn remaining_len(&self) -> Option<u64> {
+        (**self).remaining_len()
+    }
+}
+
+/// Processes the trailing partial chunk of a buffer.
+fn process_tail<C: Cipher + ?Sized>(cipher: &mut C, tail: &mut [u8]) {
+    if tail.is_empty() {
+        return;
+    }
+
+    let mut chunk = DataChunk::default();
+    chunk.as_mut_slice()[..tail.len()].copy_from_slice(tail);
+    cipher.process_chunk(&mut chunk);
+    tail.copy_from_slice(&chunk.as_ref()[..tail.len()]);
 }
 
 #[inline(always)]
@@ -96,14 +385,136 @@ fn rotate_key(key: &mut DataChunk, mut sum: u8) {
     key.0.rotate_left(1);
 }
 
+// The batch implementation below treats a chunk as a little-endian `u64`
+// word (byte `i` of the chunk is bits `8 * i..8 * i + 8` of the word), so
+// that the per-byte operations of the algorithm can be done for all bytes
+// at once (SWAR).
+
+const LOW_BITS: u64 = 0x0101_0101_0101_0101;
+
+/// Per-byte masks selecting the bytes to rotate by 1, 2 and 4 bits when
+/// rotating the byte `i` left by `i` bits.
+const KEY_ROTATION_MASKS: [u64; 3] = [
+    0xff00_ff00_ff00_ff00,
+    0xffff_0000_ffff_0000,
+    0xffff_ffff_0000_0000,
+];
+
+fn as_arrays<'a, I>(chunks: I) -> impl Iterator<Item = &'a mut [u8; CHUNK_SIZE]>
+where
+    I: Iterator<Item = &'a mut [u8]>,
+{
+    chunks.map(|bytes| <&mut [u8; CHUNK_SIZE]>::try_from(bytes).expect("the slice is a chunk"))
+}
+
+#[inline(always)]
+fn process_words<'a, I>(key: &mut DataChunk, chunks: I, process: fn(&mut u64, u64) -> u64)
+where
+    I: Iterator<Item = &'a mut [u8; CHUNK_SIZE]>,
+{
+    let mut key_word = u64::from_le_bytes(key.0);
+    for bytes in chunks {
+        *bytes = process(&mut key_word, u64::from_le_bytes(*bytes)).to_le_bytes();
+    }
+    key.0 = key_word.to_le_bytes();
+}
+
+#[inline(always)]
+fn encrypt_word(key: &mut u64, word: u64) -> u64 {
+    let c_sum = word_sum(word);
+    let word = encrypt_with_key(*key, word);
+    rotate_key_word(key, c_sum);
+    word
+}
+
+#[inline(always)]
+fn decrypt_word(key: &mut u64, word: u64) -> u64 {
+    let word = decrypt_with_key(*key, word);
+    rotate_key_word(key, word_sum(word));
+    word
+}
+
+#[inline(always)]
+fn encrypt_with_key(key: u64, word: u64) -> u64 {
+    rotate_bytes_left(word, key_factors(key)) ^ key
+}
+
+#[inline(always)]
+fn decrypt_with_key(key: u64, word: u64) -> u64 {
+    // Rotating right by `n` is rotating left by `8 - n`.
+    let factors = (LOW_BITS * 8 - key_factors(key)) & (LOW_BITS * 7);
+    rotate_bytes_left(word ^ key, factors)
+}
+
+/// Returns the rotation amount of each byte (`factor_a` in the spec).
+#[inline(always)]
+fn key_factors(key: u64) -> u64 {
+    (key ^ (word_sum(key) as u64 * LOW_BITS)) & (LOW_BITS * 7)
+}
+
+/// Derives the key of the chunk at `index` for the seekable variant,
+/// which is the `index + 1`-th output of SplitMix64 seeded with the
+/// initial key.
+#[inline(always)]
+fn chunk_key(initial_key: u64, index: u64) -> u64 {
+    let mut z = initial_key.wrapping_add(index.wrapping_add(1).wrapping_mul(0x9e37_79b9_7f4a_7c15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[inline(always)]
+fn word_sum(mut word: u64) -> u8 {
+    word ^= word >> 32;
+    word ^= word >> 16;
+    word ^= word >> 8;
+    word as u8
+}
+
+/// Rotates each byte left by the amount in the same byte of `factors`,
+/// which must be less than 8.
+#[inline(always)]
+fn rotate_bytes_left(mut word: u64, factors: u64) -> u64 {
+    for bit in 0..3 {
+        let mask = ((factors >> bit) & LOW_BITS) * 0xff;
+        word = (rotate_all_bytes_left(word, 1 << bit) & mask) | (word & !mask);
+    }
+    word
+}
+
+/// Rotates all bytes left by `n` bits, where `n` is in `1..8`.
+#[inline(always)]
+fn rotate_all_bytes_left(word: u64, n: u32) -> u64 {
+    let high_mask = LOW_BITS * (0xff_u8 << n) as u64;
+    ((word << n) & high_mask) | ((word >> (8 - n)) & !high_mask)
+}
+
+#[inline(always)]
+fn rotate_key_word(key: &mut u64, sum: u8) {
+    let mut sums = sum as u64 * LOW_BITS;
+    for (bit, mask) in KEY_ROTATION_MASKS.iter().enumerate() {
+        sums = (rotate_all_bytes_left(sums, 1 << bit) & mask) | (sums & !mask);
+    }
+    *key ^= sums;
+
+    // Rotate the chunk left by byte.
+    *key = key.rotate_right(8);
+}
+
 #[cfg(test)]
 mod tests {
     extern crate test;
     use test::Bencher;
 
-    use super::{Cipher, EncryptState};
+    use std::io::Cursor;
+
+    use super::{
+        Cipher, CipherSnapshot, DecryptState, EncryptState, ResumableCipher, SeekableCipher,
+        SeekableDecryptState, SeekableEncryptState,
+    };
     use crate::key_init::KeyInit;
-    use crate::types::DataChunk;
+    use crate::types::{DataChunk, CHUNK_SIZE};
+    use crate::Pipeline;
 
     #[test]
     fn test_encrypt() {
@@ -117,6 +528,166 @@ mod tests {
         assert_eq!(plain_data_chunk_2.0, [98, 28, 137, 145, 34, 66, 130, 0]);
     }
 
+    #[test]
+    fn test_process_buffer() {
+        let seed_phrase = "you can not see me";
+        for len in 0..40 {
+            let plain: Vec<u8> = (0..len as u8).collect();
+
+            let mut expected = vec![];
+            Pipeline::new()
+                .process(
+                    Cursor::new(&plain),
+                    &mut expected,
+                    EncryptState::with_seed_phrase(seed_phrase),
+                    None,
+                )
+                .expect("failed to encrypt");
+
+            let mut data = plain.clone();
+            EncryptState::with_seed_phrase(seed_phrase).process_buffer(&mut data);
+            assert_eq!(data, expected, "mismatched output with length {}", len);
+
+            DecryptState::with_seed_phrase(seed_phrase).process_buffer(&mut data);
+            assert_eq!(data, plain);
+        }
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let key = DataChunk::from([1, 2, 4, 8, 16, 32, 64, 128]);
+        let plain: Vec<u8> = (0..203u32).map(|i| (i * 31 + 7) as u8).collect();
+
+        fn check<C: ResumableCipher + KeyInit>(key: DataChunk, plain: &[u8]) {
+            let mut expected = plain.to_vec();
+            C::with_key(key).process_buffer(&mut expected);
+
+            let mut data = plain.to_vec();
+            let (head, tail) = data.split_at_mut(96);
+            let mut cipher = C::with_key(key);
+            cipher.process_buffer(head);
+            let snapshot = CipherSnapshot::from_bytes(&cipher.snapshot().to_bytes());
+            assert_eq!(snapshot, cipher.snapshot());
+
+            let mut resumed = C::from_snapshot(&snapshot);
+            resumed.process_buffer(tail);
+            assert_eq!(data, expected);
+        }
+
+        check::<EncryptState>(key, &plain);
+        check::<DecryptState>(key, &plain);
+        check::<SeekableEncryptState>(key, &plain);
+        check::<SeekableDecryptState>(key, &plain);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_snapshot_serde() {
+        let mut cipher = SeekableEncryptState::with_key_from([1, 2, 4, 8, 16, 32, 64, 128]);
+        cipher.seek_chunk(42).expect("failed to seek");
+        let snapshot = cipher.snapshot();
+
+        let json = serde_json::to_string(&snapshot).expect("failed to serialize");
+        let decoded: CipherSnapshot = serde_json::from_str(&json).expect("failed to deserialize");
+        assert_eq!(decoded, snapshot);
+    }
+
+    #[test]
+    fn test_batch_matches_scalar() {
+        let mut state = 0x1234_5678_u32;
+        let plain: Vec<DataChunk> = (0..1000)
+            .map(|_| {
+                let mut chunk = DataChunk::default();
+                for byte in chunk.as_mut_slice() {
+                    // Xorshift32 for some arbitrary data.
+                    state ^= state << 13;
+                    state ^= state >> 17;
+                    state ^= state << 5;
+                    *byte = state as u8;
+                }
+                chunk
+            })
+            .collect();
+
+        let mut scalar_state = EncryptState::with_key_from([1, 2, 4, 8, 16, 32, 64, 128]);
+        let mut expected = plain.clone();
+        for chunk in &mut expected {
+            scalar_state.process_chunk(chunk);
+        }
+
+        let mut batch_state = EncryptState::with_key_from([1, 2, 4, 8, 16, 32, 64, 128]);
+        let mut cipher_chunks = plain.clone();
+        let (head, rest) = cipher_chunks.split_at_mut(333);
+        batch_state.process_chunks(head);
+        batch_state.process_chunks(rest);
+        assert_eq!(cipher_chunks, expected);
+
+        let mut scalar_state = DecryptState::with_key_from([1, 2, 4, 8, 16, 32, 64, 128]);
+        let mut batch_state = DecryptState::with_key_from([1, 2, 4, 8, 16, 32, 64, 128]);
+        let mut decrypted = cipher_chunks.clone();
+        batch_state.process_chunks(&mut decrypted);
+        for chunk in &mut cipher_chunks {
+            scalar_state.process_chunk(chunk);
+        }
+        assert_eq!(decrypted, plain);
+        assert_eq!(cipher_chunks, plain);
+    }
+
+    #[test]
+    fn test_seekable() {
+        let key = DataChunk::from([1, 2, 4, 8, 16, 32, 64, 128]);
+        let plain: Vec<u8> = (0..203u32).map(|i| (i * 31 + 7) as u8).collect();
+
+        let mut cipher_text = plain.clone();
+        SeekableEncryptState::with_key(key).process_buffer(&mut cipher_text);
+        assert_ne!(cipher_text, plain);
+
+        // Identical chunks are encrypted differently.
+        let mut zeros = [0; 16];
+        SeekableEncryptState::with_key(key).process_buffer(&mut zeros);
+        assert_ne!(zeros[0..8], zeros[8..16]);
+
+        for start_chunk in [0, 1, 7, 25] {
+            let start = start_chunk * 8;
+            let mut decrypt = SeekableDecryptState::with_key(key);
+            decrypt
+                .seek_chunk(start_chunk as u64)
+                .expect("failed to seek");
+            let mut decrypted = cipher_text[start..].to_vec();
+            decrypt.process_buffer(&mut decrypted);
+            assert_eq!(decrypted, plain[start..]);
+            assert_eq!(
+                decrypt.chunk_index(),
+                (plain.len() as u64).div_ceil(8),
+                "the tail is counted as a chunk"
+            );
+        }
+    }
+
+    #[bench]
+    fn bench_scalar_chunks(b: &mut Bencher) {
+        let mut state = EncryptState::with_key_from(test::black_box([1, 2, 4, 8, 16, 32, 64, 128]));
+        let mut chunks = vec![DataChunk::from(test::black_box([1, 1, 1, 1, 2, 2, 2, 2])); 4096];
+
+        b.bytes = (chunks.len() * CHUNK_SIZE) as u64;
+        b.iter(|| {
+            for chunk in chunks.iter_mut() {
+                state.process_chunk(chunk);
+            }
+        });
+    }
+
+    #[bench]
+    fn bench_batch_chunks(b: &mut Bencher) {
+        let mut state = EncryptState::with_key_from(test::black_box([1, 2, 4, 8, 16, 32, 64, 128]));
+        let mut chunks = vec![DataChunk::from(test::black_box([1, 1, 1, 1, 2, 2, 2, 2])); 4096];
+
+        b.bytes = (chunks.len() * CHUNK_SIZE) as u64;
+        b.iter(|| {
+            state.process_chunks(&mut chunks);
+        });
+    }
+
     #[bench]
     fn bench_our_algorithm(b: &mut Bencher) {
         let mut state = EncryptState::with_key_from(test::black_box([1, 2, 4, 8, 16, 32, 64, 128]));
@@ -0,0 +1,260 @@
+//! Cipher backed by ChaCha20.
+
+use chacha20::cipher::{KeyIvInit, StreamCipher, StreamCipherSeek};
+use chacha20::ChaCha20;
+use sha2::Sha256;
+
+use super::{Cipher, SeekableCipher};
+use crate::error::{Error, Result};
+use crate::key_init::KeyDerivation;
+use crate::types::{DataChunk, CHUNK_SIZE};
+
+/// Length of the ChaCha20 key in bytes.
+const KEY_LEN: usize = 32;
+/// Length of the ChaCha20 nonce in bytes.
+const NONCE_LEN: usize = 12;
+
+const KEY_CONTEXT: &[u8] = b"yafo chacha20 key";
+
+/// Length of the keystream in bytes, which is limited by the 32-bit
+/// block counter.
+const KEYSTREAM_LEN: u64 = u32::MAX as u64 * 64;
+
+/// Cipher that XORs the data with the ChaCha20 keystream.
+///
+/// Unlike the obfuscation algorithms, this is a standard stream cipher,
+/// so the same state serves both encryption and decryption. The 64-bit
+/// initial key of the other algorithms is too short for it, so the
+/// 256-bit key is derived from the seed phrase instead, see
+/// [`ChaCha20State::with_derived_key`].
+///
+/// For the same reason, it doesn't implement [`KeyInit`], and it doesn't
+/// implement [`ResumableCipher`] either, since a [`CipherSnapshot`] can't
+/// hold its key. So it can't be used with
+/// [`Pipeline::process_file_journaled`], but it works with the other
+/// [`Pipeline`] APIs, including [`Pipeline::process_file_parallel`].
+///
+/// The keystream is limited to about 256 GiB, see
+/// [`Cipher::remaining_len`]. [`Pipeline`] fails with
+/// [`Error::InvalidInput`] for longer inputs, before touching the file
+/// when processing in place.
+///
+/// A key and nonce pair must never be reused for different data, or the
+/// data can be recovered by XORing the outputs.
+///
+/// [`KeyInit`]: crate::key_init::KeyInit
+/// [`ResumableCipher`]: crate::cipher::ResumableCipher
+/// [`CipherSnapshot`]: crate::cipher::CipherSnapshot
+/// [`Pipeline`]: crate::pipeline::Pipeline
+/// [`Pipeline::process_file_journaled`]: crate::pipeline::Pipeline::process_file_journaled
+/// [`Pipeline::process_file_parallel`]: crate::pipeline::Pipeline::process_file_parallel
+pub struct ChaCha20State {
+    key: [u8; KEY_LEN],
+    nonce: [u8; NONCE_LEN],
+    inner: ChaCha20,
+}
+
+impl ChaCha20State {
+    /// Creates the cipher with the given key and nonce.
+    pub fn new(key: [u8; KEY_LEN], nonce: [u8; NONCE_LEN]) -> Self {
+        Self {
+            key,
+            nonce,
+            inner: ChaCha20::new(&key.into(), &nonce.into()),
+        }
+    }
+
+    /// Creates the cipher with the key derived from the seed phrase by
+    /// the salted derivation, and the nonce taken from the salt.
+    ///
+    /// The salt is random for every container, so neither the key nor
+    /// the nonce is reused. Fails with [`Error::InvalidInput`] if the
+    /// derivation is not salted.
+    pub fn with_derived_key(phrase: &str, derivation: &KeyDerivation) -> Result<Self> {
+        let KeyDerivation::Pbkdf2 { salt, iterations } = derivation else {
+            return Err(Error::InvalidInput(
+                "chacha20 requires a salted key derivation",
+            ));
+        };
+
+        // The context separates the key from the initial key, which is
+        // derived from the same phrase and salt.
+        let mut key = [0; KEY_LEN];
+        let context_salt = [&salt[..], KEY_CONTEXT].concat();
+        pbkdf2::pbkdf2_hmac::<Sha256>(phrase.as_bytes(), &context_salt, *iterations, &mut key);
+        let mut nonce = [0; NONCE_LEN];
+        nonce.copy_from_slice(&salt[..NONCE_LEN]);
+        Ok(Self::new(key, nonce))
+    }
+}
+
+// The core of `ChaCha20` is not `Clone`, so the state is rebuilt.
+impl Clone for ChaCha20State {
+    fn clone(&self) -> Self {
+        let mut cloned = Self::new(self.key, self.nonce);
+        cloned.inner.seek(self.inner.current_pos::<u64>());
+        cloned
+    }
+}
+
+impl Cipher for ChaCha20State {
+    fn process_chunk(&mut self, chunk: &mut DataChunk) {
+        self.inner.apply_keystream(chunk.as_mut_slice());
+    }
+
+    fn process_buffer(&mut self, data: &mut [u8]) {
+        // The keystream doesn't depend on the data, so the partial tail
+        // is processed the same way as a padded chunk.
+        self.inner.apply_keystream(data);
+    }
+
+    fn process_chunks(&mut self, chunks: &mut [DataChunk]) {
+        for chunk in chunks {
+            self.process_chunk(chunk);
+        }
+    }
+
+    fn remaining_len(&self) -> Option<u64> {
+        Some(KEYSTREAM_LEN.saturating_sub(self.inner.current_pos::<u64>()))
+    }
+}
+
+impl SeekableCipher for ChaCha20State {
+    fn chunk_index(&self) -> u64 {
+        self.inner.current_pos::<u64>().div_ceil(CHUNK_SIZE as u64)
+    }
+
+    fn seek_chunk(&mut self, index: u64) -> Result<()> {
+        let pos = index
+            .checked_mul(CHUNK_SIZE as u64)
+            .ok_or(Error::InvalidInput("the chunk index is out of range"))?;
+        self.inner
+            .try_seek(pos)
+            .map_err(|_| Error::InvalidInput("the chunk index is beyond the keystream"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::assert_matches::assert_matches;
+    use std::fs;
+    use std::io::Cursor;
+
+    use super::{ChaCha20State, KEYSTREAM_LEN};
+    use crate::cipher::{Cipher, SeekableCipher};
+    use crate::error::Error;
+    use crate::key_init::KeyDerivation;
+    use crate::pipeline::Pipeline;
+    use crate::test_utils::{payload, temp_file_path, SEED_PHRASE};
+    use crate::types::{DataChunk, CHUNK_SIZE};
+
+    fn derivation(salt: u8) -> KeyDerivation {
+        KeyDerivation::Pbkdf2 {
+            salt: [salt; 16],
+            iterations: 16,
+        }
+    }
+
+    fn cipher(salt: u8) -> ChaCha20State {
+        ChaCha20State::with_derived_key(SEED_PHRASE, &derivation(salt)).expect("invalid derivation")
+    }
+
+    #[test]
+    fn round_trip() {
+        let plain = payload();
+
+        let mut encrypted = vec![];
+        Pipeline::new()
+            .process(Cursor::new(&plain), &mut encrypted, cipher(42), None)
+            .expect("failed to encrypt");
+        assert_ne!(encrypted, plain);
+
+        // Processing chunk by chunk yields the same keystream.
+        let mut chunked = plain.clone();
+        let mut chunk_cipher = cipher(42);
+        for bytes in chunked.chunks_mut(CHUNK_SIZE) {
+            let mut chunk = DataChunk::default();
+            chunk.as_mut_slice()[..bytes.len()].copy_from_slice(bytes);
+            chunk_cipher.process_chunk(&mut chunk);
+            bytes.copy_from_slice(&chunk.as_ref()[..bytes.len()]);
+        }
+        assert_eq!(chunked, encrypted);
+
+        let mut decrypted = vec![];
+        Pipeline::new()
+            .process(Cursor::new(&encrypted), &mut decrypted, cipher(42), None)
+            .expect("failed to decrypt");
+        assert_eq!(decrypted, plain);
+
+        let other_key = ChaCha20State::with_derived_key("you can see me", &derivation(42));
+        let mut other = plain.clone();
+        other_key.unwrap().process_buffer(&mut other);
+        assert_ne!(other, encrypted);
+    }
+
+    #[test]
+    fn salt_changes_keystream() {
+        let mut first = [0; 64];
+        cipher(42).process_buffer(&mut first);
+        let mut second = [0; 64];
+        cipher(43).process_buffer(&mut second);
+        assert_ne!(first, second);
+
+        assert_matches!(
+            ChaCha20State::with_derived_key(SEED_PHRASE, &KeyDerivation::V1).err(),
+            Some(Error::InvalidInput(_))
+        );
+    }
+
+    #[test]
+    fn seek() {
+        let plain = payload();
+        let mut expected = plain.clone();
+        cipher(42).process_buffer(&mut expected);
+
+        let offset = CHUNK_SIZE * 37;
+        let mut seeked = cipher(42);
+        seeked.seek_chunk(37).expect("failed to seek");
+        assert_eq!(seeked.chunk_index(), 37);
+        let mut tail = plain[offset..].to_vec();
+        seeked.process_buffer(&mut tail);
+        assert_eq!(tail, expected[offset..]);
+
+        let mut cloned = cipher(42);
+        let mut data = plain.clone();
+        cloned.process_buffer(&mut data[..offset]);
+        cloned.clone().process_buffer(&mut data[offset..]);
+        assert_eq!(data, expected);
+
+        let mut seeked = cipher(42);
+        assert_matches!(seeked.seek_chunk(u64::MAX), Err(Error::InvalidInput(_)));
+        assert_matches!(seeked.seek_chunk(1 << 40), Err(Error::InvalidInput(_)));
+    }
+
+    #[test]
+    fn keystream_end() {
+        let mut cipher = cipher(42);
+        assert_eq!(cipher.remaining_len(), Some(KEYSTREAM_LEN));
+
+        let last_chunk = KEYSTREAM_LEN / CHUNK_SIZE as u64 - 1;
+        cipher.seek_chunk(last_chunk).expect("failed to seek");
+        assert_eq!(cipher.remaining_len(), Some(CHUNK_SIZE as u64));
+        cipher.process_buffer(&mut [0; CHUNK_SIZE]);
+        assert_eq!(cipher.remaining_len(), Some(0));
+
+        // The pipeline fails instead of running out of the keystream, and
+        // leaves the file untouched.
+        cipher.seek_chunk(last_chunk).expect("failed to seek");
+        let input = Cursor::new(payload());
+        let result = Pipeline::new().process(input, &mut vec![], cipher.clone(), None);
+        assert_matches!(result, Err(Error::InvalidInput(_)));
+
+        let path = temp_file_path("chacha_keystream_end");
+        fs::write(&path, payload()).expect("failed to write file");
+        let result = Pipeline::new().process_file(&path, cipher);
+        let data = fs::read(&path).expect("failed to read file");
+        fs::remove_file(&path).expect("failed to remove file");
+        assert_matches!(result, Err(Error::InvalidInput(_)));
+        assert_eq!(data, payload());
+    }
+}
@@ -0,0 +1,121 @@
+//! Error type of the library.
+//!
+//! Most operations return [`Result`] with the [`Error`] defined here.
+//! Adapters that implement [`std::io::Read`] or [`std::io::Write`] (e.g.
+//! [`CipherReader`]) still return [`std::io::Error`], which can carry an
+//! [`Error`] inside. Converting such an I/O error back with [`From`]
+//! recovers the original error.
+//!
+//! [`CipherReader`]: crate::stream::CipherReader
+
+use std::fmt::{self, Display, Formatter};
+use std::io::{Error as IoError, ErrorKind as IoErrorKind};
+
+/// A specialized result type for yafo operations.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// The error type for yafo operations.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// An I/O operation failed.
+    Io(IoError),
+    /// The writer caught up with the reader while processing a file in
+    /// place, which would overwrite unread bytes.
+    DuplexOverrun,
+    /// The data is not in the expected format, e.g. not a yafo container.
+    InvalidFormat(String),
+    /// The key doesn't match the one the data is encrypted with.
+    WrongKey,
+    /// The integrity tag doesn't match the data, which has been tampered
+    /// with or corrupted.
+    TagMismatch,
+    /// The data is written by an unsupported format version.
+    UnsupportedVersion(u8),
+    /// The processing was cancelled, after transforming the given number
+    /// of bytes.
+    Cancelled { bytes_processed: u64 },
+    /// The operation is not supported with the given arguments.
+    InvalidInput(&'static str),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            // The I/O error is the source, so it's not repeated here.
+            Self::Io(_) => f.write_str("I/O error"),
+            Self::DuplexOverrun => f.write_str("the writer overran the reader"),
+            Self::InvalidFormat(msg) => f.write_str(msg),
+            Self::WrongKey => f.write_str("wrong key"),
+            Self::TagMismatch => {
+                f.write_str("integrity check failed, the data is tampered with or corrupted")
+            }
+            Self::UnsupportedVersion(version) => write!(f, "unsupported version {}", version),
+            Self::Cancelled { bytes_processed } => {
+                write!(f, "cancelled after processing {} bytes", bytes_processed)
+            }
+            Self::InvalidInput(msg) => f.write_str(msg),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<IoError> for Error {
+    fn from(err: IoError) -> Self {
+        if err.get_ref().is_some_and(|inner| inner.is::<Error>()) {
+            let inner = err
+                .into_inner()
+                .expect("the error should have an inner error");
+            return *inner
+                .downcast()
+                .expect("the inner error should be yafo::Error");
+        }
+        Self::Io(err)
+    }
+}
+
+impl From<Error> for IoError {
+    fn from(err: Error) -> Self {
+        let kind = match err {
+            Error::Io(err) => return err,
+            Error::InvalidFormat(_)
+            | Error::WrongKey
+            | Error::TagMismatch
+            | Error::UnsupportedVersion(_) => IoErrorKind::InvalidData,
+            Error::InvalidInput(_) => IoErrorKind::InvalidInput,
+            Error::DuplexOverrun | Error::Cancelled { .. } => IoErrorKind::Other,
+        };
+        IoError::new(kind, err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::assert_matches::assert_matches;
+    use std::error::Error as _;
+    use std::io::{Error as IoError, ErrorKind as IoErrorKind};
+
+    use super::Error;
+
+    #[test]
+    fn io_error_conversion() {
+        let err = IoError::from(Error::WrongKey);
+        assert_eq!(err.kind(), IoErrorKind::InvalidData);
+        assert_matches!(Error::from(err), Error::WrongKey);
+
+        let err = Error::from(IoError::from(IoErrorKind::NotFound));
+        assert_eq!(err.to_string(), "I/O error");
+        assert!(err.source().is_some());
+        let err = IoError::from(err);
+        assert_eq!(err.kind(), IoErrorKind::NotFound);
+        assert!(err.get_ref().is_none());
+    }
+}
@@ -2,39 +2,165 @@
 
 use std::ffi::CStr;
 use std::os::raw::c_char;
+use std::ptr;
 use std::sync::Mutex;
 
-use crate::cipher::{Cipher, DecryptState, EncryptState};
+use crate::algorithm::Algorithm;
+use crate::cipher::Cipher;
+use crate::error::Error;
 use crate::pipeline::Pipeline;
+use crate::types::DataChunk;
 use crate::KeyInit;
 
 pub const ERR_OK: i32 = 0;
 pub const ERR_IO_ERROR: i32 = 1;
 pub const ERR_INVALID_PATH: i32 = 2;
+pub const ERR_DUPLEX_OVERRUN: i32 = 3;
+pub const ERR_INVALID_FORMAT: i32 = 4;
+pub const ERR_WRONG_KEY: i32 = 5;
+pub const ERR_UNSUPPORTED_VERSION: i32 = 6;
+pub const ERR_CANCELLED: i32 = 7;
+pub const ERR_INVALID_INPUT: i32 = 8;
+pub const ERR_TAG_MISMATCH: i32 = 9;
+
+/// Maps the error to the error code returned by the C APIs.
+fn error_code(err: &Error) -> i32 {
+    match err {
+        Error::Io(_) => ERR_IO_ERROR,
+        Error::DuplexOverrun => ERR_DUPLEX_OVERRUN,
+        Error::InvalidFormat(_) => ERR_INVALID_FORMAT,
+        Error::WrongKey => ERR_WRONG_KEY,
+        Error::TagMismatch => ERR_TAG_MISMATCH,
+        Error::UnsupportedVersion(_) => ERR_UNSUPPORTED_VERSION,
+        Error::Cancelled { .. } => ERR_CANCELLED,
+        Error::InvalidInput(_) => ERR_INVALID_INPUT,
+    }
+}
 
 pub struct Handle {
     cipher: Mutex<Box<dyn Cipher>>,
 }
 
+/// Creates a handle of the default algorithm from the seed phrase.
+///
+/// # Safety
+///
+/// `seed_phrase` must point to a valid nul-terminated string.
 #[no_mangle]
-pub extern "C" fn yafo_create_handle(seed_phrase: *const c_char, decrypt: bool) -> *mut Handle {
-    let seed_phrase_str = unsafe { CStr::from_ptr(seed_phrase) }.to_string_lossy();
-    let cipher: Box<dyn Cipher> = {
-        if decrypt {
-            Box::new(DecryptState::with_seed_phrase(seed_phrase_str.as_ref()))
-        } else {
-            Box::new(EncryptState::with_seed_phrase(seed_phrase_str.as_ref()))
+pub unsafe extern "C" fn yafo_create_handle(
+    seed_phrase: *const c_char,
+    decrypt: bool,
+) -> *mut Handle {
+    create_handle(
+        unsafe { seed_phrase_key(seed_phrase) },
+        Algorithm::V1,
+        decrypt,
+    )
+}
+
+/// Creates a handle of the algorithm with the given name (e.g. `v1`),
+/// see [`Algorithm`] for the names.
+///
+/// Returns null if the algorithm is unknown or not available in this
+/// build. The key is derived without a salt, so algorithms that need a
+/// salted derivation (e.g. `chacha20`) are refused as well.
+///
+/// # Safety
+///
+/// `seed_phrase` and `algorithm` must point to valid nul-terminated
+/// strings.
+#[no_mangle]
+pub unsafe extern "C" fn yafo_create_handle_with_algorithm(
+    seed_phrase: *const c_char,
+    algorithm: *const c_char,
+    decrypt: bool,
+) -> *mut Handle {
+    match unsafe { parse_algorithm(algorithm) } {
+        Some(algorithm) => {
+            create_handle(unsafe { seed_phrase_key(seed_phrase) }, algorithm, decrypt)
         }
+        None => ptr::null_mut(),
+    }
+}
+
+/// Creates a handle of the algorithm with the given name (e.g. `v1`)
+/// from a raw key, which is encoded as 16 hex digits or in base64.
+///
+/// Returns null if the key is invalid, or the algorithm is unknown or not
+/// available in this build. Algorithms that derive their own key from the
+/// seed phrase (e.g. `chacha20`) are refused as well.
+///
+/// # Safety
+///
+/// `raw_key` and `algorithm` must point to valid nul-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn yafo_create_handle_with_raw_key(
+    raw_key: *const c_char,
+    algorithm: *const c_char,
+    decrypt: bool,
+) -> *mut Handle {
+    let raw_key = unsafe { CStr::from_ptr(raw_key) }.to_str();
+    let Some(key) = raw_key
+        .ok()
+        .and_then(|key| DataChunk::with_raw_key(key).ok())
+    else {
+        return ptr::null_mut();
+    };
+    match unsafe { parse_algorithm(algorithm) } {
+        Some(algorithm) => create_handle(key, algorithm, decrypt),
+        None => ptr::null_mut(),
+    }
+}
+
+/// Parses the name of an algorithm that can be keyed by the initial key,
+/// since no salt is given to the C APIs.
+///
+/// # Safety
+///
+/// `algorithm` must point to a valid nul-terminated string.
+unsafe fn parse_algorithm(algorithm: *const c_char) -> Option<Algorithm> {
+    let algorithm = unsafe { CStr::from_ptr(algorithm) }.to_str();
+    algorithm
+        .ok()
+        .and_then(|name| name.parse::<Algorithm>().ok())
+        .filter(|algorithm| !algorithm.needs_seed_phrase())
+}
+
+fn create_handle(key: DataChunk, algorithm: Algorithm, decrypt: bool) -> *mut Handle {
+    let cipher = if decrypt {
+        algorithm.decryptor(key)
+    } else {
+        algorithm.encryptor(key)
+    };
+    let Ok(cipher) = cipher else {
+        return ptr::null_mut();
     };
 
     let handle = Handle {
-        cipher: Mutex::new(cipher),
+        cipher: Mutex::new(cipher as Box<dyn Cipher>),
     };
     Box::into_raw(Box::new(handle))
 }
 
+/// Derives the initial key from the seed phrase.
+///
+/// # Safety
+///
+/// `seed_phrase` must point to a valid nul-terminated string.
+unsafe fn seed_phrase_key(seed_phrase: *const c_char) -> DataChunk {
+    let seed_phrase_str = unsafe { CStr::from_ptr(seed_phrase) }.to_string_lossy();
+    DataChunk::with_seed_phrase(seed_phrase_str.as_ref())
+}
+
+/// Processes the file in place with the handle.
+///
+/// # Safety
+///
+/// `handle` must be a live handle returned by one of the
+/// `yafo_create_handle*` functions, and `path` must point to a valid
+/// nul-terminated string.
 #[no_mangle]
-pub extern "C" fn yafo_process_file(handle: *mut Handle, path: *const c_char) -> i32 {
+pub unsafe extern "C" fn yafo_process_file(handle: *mut Handle, path: *const c_char) -> i32 {
     let path_str = match unsafe { CStr::from_ptr(path) }.to_str() {
         Ok(str) => str,
         _ => return ERR_INVALID_PATH,
@@ -46,14 +172,63 @@ pub extern "C" fn yafo_process_file(handle: *mut Handle, path: *const c_char) ->
     let pipeline = Pipeline::new();
     match pipeline.process_file(path_str, locked_cipher.as_mut()) {
         Ok(_) => ERR_OK,
-        Err(_) => ERR_IO_ERROR,
+        Err(err) => error_code(&err),
     }
 }
 
+/// Destroys the handle.
+///
+/// # Safety
+///
+/// `handle` must be a live handle returned by one of the
+/// `yafo_create_handle*` functions, and must not be used afterwards.
 #[no_mangle]
-pub extern "C" fn yafo_destroy_handle(handle: *mut Handle) {
+pub unsafe extern "C" fn yafo_destroy_handle(handle: *mut Handle) {
     let handle = unsafe { Box::from_raw(handle) };
 
     // Just to emphasize this operation.
     drop(handle);
 }
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CString;
+
+    use super::{
+        yafo_create_handle_with_algorithm, yafo_create_handle_with_raw_key, yafo_destroy_handle,
+    };
+
+    #[test]
+    fn create_handle_with_algorithm() {
+        let phrase = CString::new("you can not see me").unwrap();
+        let raw_key = CString::new("0001020304050607").unwrap();
+        for (name, available) in [
+            ("v1", true),
+            ("SEEKABLE", true),
+            ("chacha20", false),
+            ("v0", false),
+        ] {
+            let name = CString::new(name).unwrap();
+            unsafe {
+                let handle =
+                    yafo_create_handle_with_algorithm(phrase.as_ptr(), name.as_ptr(), false);
+                assert_eq!(!handle.is_null(), available, "{:?}", name);
+                if !handle.is_null() {
+                    yafo_destroy_handle(handle);
+                }
+
+                let handle = yafo_create_handle_with_raw_key(raw_key.as_ptr(), name.as_ptr(), true);
+                assert_eq!(!handle.is_null(), available, "{:?}", name);
+                if !handle.is_null() {
+                    yafo_destroy_handle(handle);
+                }
+            }
+        }
+
+        let invalid_key = CString::new("not a key").unwrap();
+        let name = CString::new("v1").unwrap();
+        let handle =
+            unsafe { yafo_create_handle_with_raw_key(invalid_key.as_ptr(), name.as_ptr(), false) };
+        assert!(handle.is_null());
+    }
+}
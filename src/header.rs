@@ -0,0 +1,272 @@
+//! Self-describing container header.
+//!
+//! A yafo container is the processed data prefixed with a fixed-length
+//! [`Header`]. The header records which algorithm and key derivation
+//! were used, plus a short key check value, so that a reader can tell
+//! whether a file is encrypted by yafo and whether a key is the right one
+//! before decrypting anything.
+//!
+//! The layout of the header (all integers are little-endian):
+//!
+//! | Offset | Length | Field                 |
+//! |--------|--------|-----------------------|
+//! | 0      | 4      | Magic bytes (`YAFO`)  |
+//! | 4      | 1      | Format version        |
+//! | 5      | 1      | Algorithm identifier  |
+//! | 6      | 1      | KDF identifier        |
+//! | 7      | 1      | Flags                 |
+//! | 8      | 4      | KDF iterations        |
+//! | 12     | 16     | KDF salt              |
+//! | 28     | 4      | Key check value       |
+//!
+//! Files without a header (the raw mode) are still supported by
+//! [`Pipeline`], they just carry no metadata at all.
+//!
+//! [`Pipeline`]: crate::pipeline::Pipeline
+
+use std::io::{ErrorKind as IoErrorKind, Read, Write};
+
+use crate::algorithm::Algorithm;
+use crate::error::{Error, Result};
+use crate::key_init::{KeyCheck, KeyDerivation, KeyInit, KEY_CHECK_LEN, SALT_LEN};
+use crate::types::DataChunk;
+
+/// Magic bytes at the beginning of every container.
+pub const MAGIC: [u8; 4] = *b"YAFO";
+
+/// The latest container format version.
+pub const FORMAT_VERSION: u8 = 1;
+
+/// Length of the encoded header in bytes.
+pub const HEADER_LEN: usize = 32;
+
+/// Flag indicating that an integrity tag follows the data, see the
+/// [`integrity`] module.
+///
+/// [`integrity`]: crate::integrity
+pub const FLAG_TAGGED: u8 = 0x01;
+
+/// Metadata stored in front of the processed data.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Header {
+    /// Format version of the container.
+    pub version: u8,
+    /// Cipher algorithm of the data.
+    pub algorithm: Algorithm,
+    /// Key derivation used to derive the initial key.
+    pub kdf: KeyDerivation,
+    /// Bit flags of the container, such as [`FLAG_TAGGED`].
+    pub flags: u8,
+    /// Short fingerprint of the initial key.
+    pub key_check: KeyCheck,
+}
+
+impl Header {
+    /// Creates a header for data encrypted by [`Algorithm::V1`] with
+    /// the given initial key, which is derived by `kdf`.
+    ///
+    /// Set [`Header::algorithm`] for data encrypted by other algorithms.
+    pub fn new(key: &DataChunk, kdf: KeyDerivation) -> Self {
+        Self {
+            version: FORMAT_VERSION,
+            algorithm: Algorithm::V1,
+            kdf,
+            flags: 0,
+            key_check: KeyCheck::with_key(*key),
+        }
+    }
+
+    /// Returns `true` if an integrity tag follows the data.
+    pub fn has_tag(&self) -> bool {
+        self.flags & FLAG_TAGGED != 0
+    }
+
+    /// Returns `true` if the given initial key matches the key check
+    /// value stored in this header.
+    pub fn matches_key(&self, key: &DataChunk) -> bool {
+        self.key_check.verify(key)
+    }
+
+    /// Checks the given initial key against this header.
+    ///
+    /// Fails with [`Error::WrongKey`] if the key doesn't match.
+    pub fn verify_key(&self, key: &DataChunk) -> Result<()> {
+        if self.matches_key(key) {
+            Ok(())
+        } else {
+            Err(Error::WrongKey)
+        }
+    }
+
+    /// Encodes the header into bytes.
+    pub fn to_bytes(&self) -> [u8; HEADER_LEN] {
+        let mut bytes = [0; HEADER_LEN];
+        bytes[0..4].copy_from_slice(&MAGIC);
+        bytes[4] = self.version;
+        bytes[5] = self.algorithm.id();
+        bytes[6] = self.kdf.id();
+        bytes[7] = self.flags;
+        if let KeyDerivation::Pbkdf2 { salt, iterations } = &self.kdf {
+            bytes[8..12].copy_from_slice(&iterations.to_le_bytes());
+            bytes[12..28].copy_from_slice(salt);
+        }
+        bytes[28..32].copy_from_slice(self.key_check.as_bytes());
+        bytes
+    }
+
+    /// Decodes the header from bytes.
+    ///
+    /// Fails with [`Error::InvalidFormat`] if the bytes are not a yafo
+    /// header or the algorithm or key derivation is not supported, and with
+    /// [`Error::UnsupportedVersion`] if the format version is not
+    /// supported.
+    pub fn from_bytes(bytes: &[u8; HEADER_LEN]) -> Result<Self> {
+        if bytes[0..4] != MAGIC {
+            return Err(Error::InvalidFormat("not a yafo container".into()));
+        }
+
+        let version = bytes[4];
+        if version != FORMAT_VERSION {
+            return Err(Error::UnsupportedVersion(version));
+        }
+
+        let Some(algorithm) = Algorithm::from_id(bytes[5]) else {
+            return Err(Error::InvalidFormat(format!(
+                "unsupported algorithm {}",
+                bytes[5]
+            )));
+        };
+
+        let mut iterations = [0; 4];
+        iterations.copy_from_slice(&bytes[8..12]);
+        let iterations = u32::from_le_bytes(iterations);
+        let mut salt = [0; SALT_LEN];
+        salt.copy_from_slice(&bytes[12..28]);
+        let kdf = match bytes[6] {
+            KeyDerivation::V1_ID => KeyDerivation::V1,
+            KeyDerivation::PBKDF2_ID if iterations > 0 => {
+                KeyDerivation::Pbkdf2 { salt, iterations }
+            }
+            id => {
+                return Err(Error::InvalidFormat(format!(
+                    "unsupported key derivation {}",
+                    id
+                )))
+            }
+        };
+
+        let flags = bytes[7];
+        if flags & !FLAG_TAGGED != 0 {
+            return Err(Error::InvalidFormat(format!(
+                "unsupported container flags {:#04x}",
+                flags
+            )));
+        }
+
+        let mut key_check = [0; KEY_CHECK_LEN];
+        key_check.copy_from_slice(&bytes[28..32]);
+
+        Ok(Self {
+            version,
+            algorithm,
+            kdf,
+            flags,
+            key_check: KeyCheck::from(key_check),
+        })
+    }
+
+    /// Reads and decodes a header from the given stream.
+    ///
+    /// A stream shorter than [`HEADER_LEN`] is treated as invalid data.
+    pub fn read_from<R: Read>(mut reader: R) -> Result<Self> {
+        let mut bytes = [0; HEADER_LEN];
+        reader.read_exact(&mut bytes).map_err(|err| {
+            if err.kind() == IoErrorKind::UnexpectedEof {
+                Error::InvalidFormat("not a yafo container".into())
+            } else {
+                Error::from(err)
+            }
+        })?;
+        Self::from_bytes(&bytes)
+    }
+
+    /// Encodes and writes the header to the given stream.
+    pub fn write_to<W: Write>(&self, mut writer: W) -> Result<()> {
+        writer.write_all(&self.to_bytes())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::assert_matches::assert_matches;
+    use std::io::Cursor;
+
+    use super::{Header, FLAG_TAGGED, HEADER_LEN};
+    use crate::algorithm::Algorithm;
+    use crate::error::Error;
+    use crate::key_init::{KeyCheck, KeyDerivation, KeyInit};
+    use crate::types::DataChunk;
+
+    #[test]
+    fn round_trip() {
+        let kdf = KeyDerivation::Pbkdf2 {
+            salt: [42; 16],
+            iterations: 16,
+        };
+        let key = DataChunk::with_derived_key("you can not see me", &kdf);
+        let header = Header {
+            algorithm: Algorithm::Seekable,
+            flags: FLAG_TAGGED,
+            ..Header::new(&key, kdf)
+        };
+        assert!(header.has_tag());
+
+        let mut bytes = vec![];
+        header.write_to(&mut bytes).expect("failed to write");
+        assert_eq!(bytes.len(), HEADER_LEN);
+        assert_eq!(&bytes[0..4], b"YAFO");
+
+        let decoded = Header::read_from(Cursor::new(&bytes)).expect("failed to read");
+        assert_eq!(decoded, header);
+        assert_eq!(decoded.key_check, KeyCheck::with_key(key));
+        assert!(decoded.matches_key(&key));
+        assert!(!decoded.matches_key(&DataChunk::with_derived_key("you can see me", &kdf)));
+    }
+
+    #[test]
+    fn reject_invalid_data() {
+        let err = Header::read_from(Cursor::new(b"hello")).unwrap_err();
+        assert_matches!(err, Error::InvalidFormat(_));
+
+        let err = Header::from_bytes(&[0x42; HEADER_LEN]).unwrap_err();
+        assert_matches!(err, Error::InvalidFormat(_));
+
+        let mut bytes = Header::new(&DataChunk::default(), KeyDerivation::V1).to_bytes();
+        bytes[4] = 0xff;
+        let err = Header::from_bytes(&bytes).unwrap_err();
+        assert_matches!(err, Error::UnsupportedVersion(0xff));
+
+        let mut bytes = Header::new(&DataChunk::default(), KeyDerivation::V1).to_bytes();
+        bytes[5] = 0xff;
+        let err = Header::from_bytes(&bytes).unwrap_err();
+        assert_matches!(err, Error::InvalidFormat(_));
+
+        let mut bytes = Header::new(&DataChunk::default(), KeyDerivation::V1).to_bytes();
+        bytes[6] = 0xff;
+        let err = Header::from_bytes(&bytes).unwrap_err();
+        assert_matches!(err, Error::InvalidFormat(_));
+
+        let mut bytes = Header::new(&DataChunk::default(), KeyDerivation::V1).to_bytes();
+        bytes[7] = 0x80;
+        let err = Header::from_bytes(&bytes).unwrap_err();
+        assert_matches!(err, Error::InvalidFormat(_));
+
+        let header = Header::new(&DataChunk::default(), KeyDerivation::V1);
+        assert_matches!(header.verify_key(&DataChunk::default()), Ok(()));
+        assert_matches!(
+            header.verify_key(&DataChunk::from([1; 8])),
+            Err(Error::WrongKey)
+        );
+    }
+}
@@ -0,0 +1,336 @@
+//! Integrity tags over the processed data.
+//!
+//! Yafo alone cannot tell whether the data has been truncated or
+//! modified, it just decrypts to garbage. With [`Pipeline::with_tag`],
+//! an HMAC-SHA256 tag over the encrypted output (including the container
+//! header, if any) is appended after the data. [`Pipeline::verify_tag`]
+//! checks it while decrypting, and fails with [`Error::TagMismatch`] if
+//! the data has been tampered with or corrupted.
+//!
+//! The MAC key is derived from the initial key of the cipher, so no
+//! extra secret is needed. As a result, the tag is only as strong as that
+//! 64-bit key, even for ciphers with a longer key (e.g. ChaCha20): it
+//! detects corruption and casual tampering, but an attacker who can try
+//! all the 2^64 keys can forge it.
+//!
+//! [`Pipeline::with_tag`]: crate::pipeline::Pipeline::with_tag
+//! [`Pipeline::verify_tag`]: crate::pipeline::Pipeline::verify_tag
+
+use std::io::{Read, Result as IoResult, Write};
+
+use hmac::{Hmac, Mac as _};
+use sha2::Sha256;
+
+use crate::error::{Error, Result};
+use crate::types::DataChunk;
+
+/// Length of the integrity tag in bytes.
+pub const TAG_LEN: usize = 32;
+
+/// Context mixed into the MAC key, so that it differs from other values
+/// derived from the same key.
+const MAC_KEY_CONTEXT: &[u8] = b"yafo integrity tag";
+
+/// A running MAC over the processed data.
+pub(crate) struct Mac(Hmac<Sha256>);
+
+impl Mac {
+    pub fn new(key: &DataChunk) -> Self {
+        let mut mac_key = MAC_KEY_CONTEXT.to_vec();
+        mac_key.extend_from_slice(key.as_ref());
+        Self(Hmac::new_from_slice(&mac_key).expect("HMAC accepts keys of any length"))
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+
+    pub fn finalize(self) -> [u8; TAG_LEN] {
+        self.0.finalize().into_bytes().into()
+    }
+
+    /// Checks the tag in constant time.
+    pub fn verify(self, tag: &[u8; TAG_LEN]) -> Result<()> {
+        self.0.verify_slice(tag).map_err(|_| Error::TagMismatch)
+    }
+}
+
+/// A writer that feeds the written bytes into a MAC, and appends the tag
+/// when finished.
+pub(crate) struct TagWriter<W> {
+    inner: W,
+    mac: Mac,
+}
+
+impl<W> TagWriter<W> {
+    pub fn new(inner: W, mac: Mac) -> Self {
+        Self { inner, mac }
+    }
+}
+
+impl<W: Write> TagWriter<W> {
+    /// Writes the tag and flushes the inner writer.
+    pub fn finish(mut self) -> Result<()> {
+        self.inner.write_all(&self.mac.finalize())?;
+        self.inner.flush()?;
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for TagWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+        let wr_len = self.inner.write(buf)?;
+        self.mac.update(&buf[..wr_len]);
+        Ok(wr_len)
+    }
+
+    fn flush(&mut self) -> IoResult<()> {
+        self.inner.flush()
+    }
+}
+
+/// A reader that holds back the trailing tag of the stream, and feeds
+/// the other bytes into a MAC.
+pub(crate) struct TagReader<R> {
+    inner: R,
+    mac: Mac,
+    holdback: Holdback,
+    eof: bool,
+}
+
+impl<R> TagReader<R> {
+    pub fn new(inner: R, mac: Mac) -> Self {
+        Self {
+            inner,
+            mac,
+            holdback: Holdback::default(),
+            eof: false,
+        }
+    }
+
+    /// Checks the tag at the end of the stream.
+    ///
+    /// The whole stream must be read before, otherwise this fails with
+    /// [`Error::InvalidInput`].
+    pub fn verify(self) -> Result<()> {
+        if !self.eof {
+            return Err(Error::InvalidInput("tag verified before end of input"));
+        }
+        match self.holdback.tag() {
+            Some(tag) => self.mac.verify(tag),
+            None => Err(Error::TagMismatch),
+        }
+    }
+
+    /// Handles `n` bytes freshly read into `buf`, returning the number of
+    /// bytes that can be passed on.
+    fn consume(&mut self, buf: &mut [u8], n: usize) -> usize {
+        if n == 0 {
+            self.eof = true;
+            return 0;
+        }
+        let out_len = self.holdback.push(buf, n);
+        self.mac.update(&buf[..out_len]);
+        out_len
+    }
+}
+
+impl<R: Read> Read for TagReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        while !buf.is_empty() && !self.eof {
+            let n = self.inner.read(buf)?;
+            let out_len = self.consume(buf, n);
+            if out_len > 0 {
+                return Ok(out_len);
+            }
+        }
+        Ok(0)
+    }
+}
+
+/// The last [`TAG_LEN`] bytes seen in a stream.
+#[derive(Default)]
+struct Holdback {
+    held: [u8; TAG_LEN],
+    len: usize,
+}
+
+impl Holdback {
+    /// Appends the first `n` bytes of `buf` to the stream, and replaces
+    /// the beginning of `buf` with the bytes that are no longer held back,
+    /// returning their length.
+    fn push(&mut self, buf: &mut [u8], n: usize) -> usize {
+        let total = self.len + n;
+        if total <= TAG_LEN {
+            self.held[self.len..total].copy_from_slice(&buf[..n]);
+            self.len = total;
+            return 0;
+        }
+
+        let out_len = total - TAG_LEN;
+        if n >= TAG_LEN {
+            // The new bytes contain the whole tail.
+            let mut tail = [0; TAG_LEN];
+            tail.copy_from_slice(&buf[n - TAG_LEN..n]);
+            buf.copy_within(..n - TAG_LEN, self.len);
+            buf[..self.len].copy_from_slice(&self.held[..self.len]);
+            self.held = tail;
+        } else {
+            // The held bytes are released partially.
+            let mut tail = [0; TAG_LEN];
+            tail[..TAG_LEN - n].copy_from_slice(&self.held[out_len..self.len]);
+            tail[TAG_LEN - n..].copy_from_slice(&buf[..n]);
+            buf[..out_len].copy_from_slice(&self.held[..out_len]);
+            self.held = tail;
+        }
+        self.len = TAG_LEN;
+        out_len
+    }
+
+    fn tag(&self) -> Option<&[u8; TAG_LEN]> {
+        (self.len == TAG_LEN).then_some(&self.held)
+    }
+}
+
+#[cfg(feature = "async")]
+mod async_io {
+    use std::io::Result as IoResult;
+    use std::pin::Pin;
+    use std::task::{ready, Context, Poll};
+
+    use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, ReadBuf};
+
+    use super::{TagReader, TagWriter};
+    use crate::error::Result;
+
+    impl<W: AsyncWrite + Unpin> TagWriter<W> {
+        /// Asynchronous version of [`TagWriter::finish`].
+        pub async fn finish_async(mut self) -> Result<()> {
+            self.inner.write_all(&self.mac.finalize()).await?;
+            self.inner.flush().await?;
+            Ok(())
+        }
+    }
+
+    impl<W: AsyncWrite + Unpin> AsyncWrite for TagWriter<W> {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<IoResult<usize>> {
+            let this = self.get_mut();
+            let wr_len = ready!(Pin::new(&mut this.inner).poll_write(cx, buf))?;
+            this.mac.update(&buf[..wr_len]);
+            Poll::Ready(Ok(wr_len))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<IoResult<()>> {
+            Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<IoResult<()>> {
+            Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+        }
+    }
+
+    impl<R: AsyncRead + Unpin> AsyncRead for TagReader<R> {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<IoResult<()>> {
+            let this = self.get_mut();
+            while buf.remaining() > 0 && !this.eof {
+                let unfilled = buf.initialize_unfilled();
+                let mut inner_buf = ReadBuf::new(unfilled);
+                ready!(Pin::new(&mut this.inner).poll_read(cx, &mut inner_buf))?;
+                let n = inner_buf.filled().len();
+                let out_len = this.consume(unfilled, n);
+                if out_len > 0 {
+                    buf.advance(out_len);
+                    break;
+                }
+            }
+            Poll::Ready(Ok(()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::assert_matches::assert_matches;
+    use std::io::{Cursor, Read, Write};
+
+    use super::{Mac, TagReader, TagWriter, TAG_LEN};
+    use crate::error::Error;
+    use crate::test_utils::payload;
+    use crate::types::DataChunk;
+
+    fn tagged(data: &[u8], key: &DataChunk) -> Vec<u8> {
+        let mut output = vec![];
+        let mut writer = TagWriter::new(&mut output, Mac::new(key));
+        writer.write_all(data).expect("failed to write");
+        writer.finish().expect("failed to finish");
+        output
+    }
+
+    fn untag(data: &[u8], key: &DataChunk, read_size: usize) -> (Vec<u8>, Result<(), Error>) {
+        let mut reader = TagReader::new(Cursor::new(data), Mac::new(key));
+        let mut output = vec![];
+        let mut buf = vec![0; read_size];
+        loop {
+            let rd_len = reader.read(&mut buf).expect("failed to read");
+            if rd_len == 0 {
+                break;
+            }
+            output.extend_from_slice(&buf[..rd_len]);
+        }
+        (output, reader.verify())
+    }
+
+    #[test]
+    fn round_trip() {
+        let key = DataChunk::from([1, 2, 3, 4, 5, 6, 7, 8]);
+        let data = payload();
+        let output = tagged(&data, &key);
+        assert_eq!(output.len(), data.len() + TAG_LEN);
+        assert_eq!(&output[..data.len()], data);
+
+        for read_size in [1, 7, 31, 32, 33, 100, 4096] {
+            let (untagged, result) = untag(&output, &key, read_size);
+            assert_eq!(untagged, data, "mismatched output with size {}", read_size);
+            assert_matches!(result, Ok(()));
+        }
+
+        let (untagged, result) = untag(&tagged(&[], &key), &key, 5);
+        assert!(untagged.is_empty());
+        assert_matches!(result, Ok(()));
+    }
+
+    #[test]
+    fn detect_tampering() {
+        let key = DataChunk::from([1, 2, 3, 4, 5, 6, 7, 8]);
+        let data = payload();
+        let output = tagged(&data, &key);
+
+        let mut flipped = output.clone();
+        flipped[500] ^= 1;
+        assert_matches!(untag(&flipped, &key, 64).1, Err(Error::TagMismatch));
+
+        let truncated = &output[..output.len() - 8];
+        assert_matches!(untag(truncated, &key, 64).1, Err(Error::TagMismatch));
+        assert_matches!(untag(&output[..10], &key, 64).1, Err(Error::TagMismatch));
+
+        let other_key = DataChunk::from([8, 7, 6, 5, 4, 3, 2, 1]);
+        assert_matches!(untag(&output, &other_key, 64).1, Err(Error::TagMismatch));
+    }
+
+    #[test]
+    fn verify_before_eof() {
+        let key = DataChunk::from([1, 2, 3, 4, 5, 6, 7, 8]);
+        let output = tagged(&payload(), &key);
+        let mut reader = TagReader::new(Cursor::new(&output), Mac::new(&key));
+        reader.read_exact(&mut [0; 64]).expect("failed to read");
+        assert_matches!(reader.verify(), Err(Error::InvalidInput(_)));
+    }
+}
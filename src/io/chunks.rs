@@ -0,0 +1,119 @@
+use crate::types::{DataChunk, CHUNK_SIZE};
+use crate::Cipher;
+
+/// What a reader driving [`ChunkAssembler`] should do next.
+pub(crate) enum Step {
+    /// The given number of bytes are copied to the caller's buffer.
+    Ready(usize),
+    /// Read from the inner reader into the caller's buffer, then pass the
+    /// length to [`ChunkAssembler::direct_read`].
+    ReadDirect,
+    /// Read from the inner reader into [`ChunkAssembler::unfilled`], then
+    /// pass the length to [`ChunkAssembler::chunk_read`].
+    ReadChunk,
+}
+
+/// Assembles whole chunks from reads of any size, so that the reader
+/// adapters process the data exactly like the pipeline does.
+///
+/// This holds no reader, which lets the synchronous and asynchronous
+/// adapters share it.
+pub(crate) struct ChunkAssembler {
+    chunk: DataChunk,
+    /// Unprocessed bytes in `chunk`.
+    filled: usize,
+    /// Processed bytes in `chunk` that are not yet consumed.
+    pos: usize,
+    len: usize,
+    eof: bool,
+}
+
+impl ChunkAssembler {
+    pub fn new() -> Self {
+        Self {
+            chunk: DataChunk::default(),
+            filled: 0,
+            pos: 0,
+            len: 0,
+            eof: false,
+        }
+    }
+
+    /// Discards all pending bytes, e.g. after seeking the inner reader.
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Consumes the processed bytes into `buf`, or tells what to read.
+    pub fn next_step(&mut self, buf: &mut [u8]) -> Step {
+        if buf.is_empty() {
+            return Step::Ready(0);
+        }
+        if self.pos < self.len {
+            let len = (self.len - self.pos).min(buf.len());
+            buf[..len].copy_from_slice(&self.chunk.as_ref()[self.pos..self.pos + len]);
+            self.pos += len;
+            return Step::Ready(len);
+        }
+        if self.eof {
+            return Step::Ready(0);
+        }
+
+        if self.filled == 0 && buf.len() >= CHUNK_SIZE {
+            Step::ReadDirect
+        } else {
+            Step::ReadChunk
+        }
+    }
+
+    /// Processes `rd_len` bytes read directly into `buf`, and keeps the
+    /// bytes that don't form a whole chunk for later.
+    ///
+    /// Returns the number of processed bytes in `buf`, or `None` if no
+    /// whole chunk is read and the caller should keep going.
+    pub fn direct_read<C>(&mut self, cipher: &mut C, buf: &mut [u8], rd_len: usize) -> Option<usize>
+    where
+        C: Cipher + ?Sized,
+    {
+        if rd_len == 0 {
+            self.eof = true;
+            return Some(0);
+        }
+
+        let whole_len = rd_len - rd_len % CHUNK_SIZE;
+        cipher.process_buffer(&mut buf[..whole_len]);
+        self.filled = rd_len - whole_len;
+        self.chunk.as_mut_slice()[..self.filled].copy_from_slice(&buf[whole_len..rd_len]);
+        (whole_len > 0).then_some(whole_len)
+    }
+
+    /// Returns the part of the chunk to read into for [`Step::ReadChunk`].
+    pub fn unfilled(&mut self) -> &mut [u8] {
+        &mut self.chunk.as_mut_slice()[self.filled..]
+    }
+
+    /// Takes `rd_len` bytes read into [`ChunkAssembler::unfilled`], and
+    /// processes the chunk once it is whole or the stream ends.
+    pub fn chunk_read<C>(&mut self, cipher: &mut C, rd_len: usize)
+    where
+        C: Cipher + ?Sized,
+    {
+        if rd_len == 0 {
+            // A short chunk can only be the tail of the stream.
+            self.eof = true;
+            self.chunk.as_mut_slice()[self.filled..].fill(0);
+        } else {
+            self.filled += rd_len;
+            if self.filled != CHUNK_SIZE {
+                return;
+            }
+        }
+
+        if self.filled > 0 {
+            cipher.process_chunk(&mut self.chunk);
+        }
+        self.pos = 0;
+        self.len = self.filled;
+        self.filled = 0;
+    }
+}
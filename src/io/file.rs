@@ -1,14 +1,34 @@
-use std::cell::Cell;
 use std::fs::File;
-use std::io::{Error as IoError, ErrorKind as IoErrorKind, Read, Result, Write};
+use std::io::{Error as IoError, Read, Result, Write};
 use std::path::Path;
-use std::rc::Rc;
 use std::result::Result as StdResult;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use crate::error::Error;
+
+/// Syncs the directory containing the given path, so that a file renamed
+/// to that path is not lost if the system crashes.
+pub(crate) fn sync_parent_dir(path: &Path) -> Result<()> {
+    // Directories can't be opened as files on other platforms.
+    #[cfg(unix)]
+    {
+        let parent = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        File::open(parent)?.sync_all()?;
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
 
 /// Creates a duplex file that can be read and written simultaneously.
 ///
 /// The bytes to be written should not be more than the bytes that
-/// are read (i.e. no overlapping is allowed).
+/// are read (i.e. no overlapping is allowed), unless the reader has
+/// reached EOF.
 pub(crate) fn duplex_file<P>(path: P) -> StdResult<(DuplexFileReader, DuplexFileWriter), IoError>
 where
     P: AsRef<Path>,
@@ -17,14 +37,15 @@ where
     let rd = File::options().read(true).open(path)?;
     let wr = File::options().write(true).open(path)?;
 
-    let state = Rc::new(DuplexFileState {
-        rd_cnt: Cell::new(0),
-        wr_cnt: Cell::new(0),
+    let state = Arc::new(DuplexFileState {
+        rd_cnt: AtomicUsize::new(0),
+        wr_cnt: AtomicUsize::new(0),
+        rd_eof: AtomicBool::new(false),
     });
 
     let reader = DuplexFileReader {
         rd,
-        state: Rc::clone(&state),
+        state: Arc::clone(&state),
     };
     let writer = DuplexFileWriter { wr, state };
     Ok((reader, writer))
@@ -33,19 +54,20 @@ where
 #[derive(Debug)]
 pub(crate) struct DuplexFileReader {
     rd: File,
-    state: Rc<DuplexFileState>,
+    state: Arc<DuplexFileState>,
 }
 
 #[derive(Debug)]
 pub(crate) struct DuplexFileWriter {
     wr: File,
-    state: Rc<DuplexFileState>,
+    state: Arc<DuplexFileState>,
 }
 
 #[derive(Debug)]
 struct DuplexFileState {
-    rd_cnt: Cell<usize>,
-    wr_cnt: Cell<usize>,
+    rd_cnt: AtomicUsize,
+    wr_cnt: AtomicUsize,
+    rd_eof: AtomicBool,
 }
 
 impl DuplexFileReader {
@@ -55,26 +77,39 @@ impl DuplexFileReader {
     }
 }
 
+impl DuplexFileWriter {
+    /// Truncates the file to the bytes written so far, which is needed
+    /// when the output is shorter than the input.
+    pub fn truncate(&mut self) -> Result<()> {
+        self.wr
+            .set_len(self.state.wr_cnt.load(Ordering::SeqCst) as u64)
+    }
+}
+
 impl Read for DuplexFileReader {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
         let rd_len = self.rd.read(buf)?;
-        self.state.rd_cnt.set(self.state.rd_cnt.get() + rd_len);
+        self.state.rd_cnt.fetch_add(rd_len, Ordering::SeqCst);
+        if rd_len == 0 && !buf.is_empty() {
+            self.state.rd_eof.store(true, Ordering::SeqCst);
+        }
         Ok(rd_len)
     }
 }
 
 impl Write for DuplexFileWriter {
     fn write(&mut self, buf: &[u8]) -> Result<usize> {
-        let wr_cnt = self.state.wr_cnt.get();
-        let rd_cnt = self.state.rd_cnt.get();
+        let wr_cnt = self.state.wr_cnt.load(Ordering::SeqCst);
+        let rd_cnt = self.state.rd_cnt.load(Ordering::SeqCst);
 
+        // Writing past the end of file cannot clobber unread bytes.
         let total_wr = wr_cnt + buf.len();
-        if total_wr > rd_cnt {
-            return Err(IoError::from(IoErrorKind::Other));
+        if total_wr > rd_cnt && !self.state.rd_eof.load(Ordering::SeqCst) {
+            return Err(IoError::from(Error::DuplexOverrun));
         }
 
         let wr_len = self.wr.write(buf)?;
-        self.state.wr_cnt.set(wr_cnt + wr_len);
+        self.state.wr_cnt.fetch_add(wr_len, Ordering::SeqCst);
         Ok(wr_len)
     }
 
@@ -1 +1,179 @@
+use std::io::{ErrorKind as IoErrorKind, Read, Result};
+
+pub(crate) mod chunks;
 pub(crate) mod file;
+
+/// Reads bytes until `buf` is full or the reader reaches EOF.
+///
+/// Unlike [`Read::read_exact`], hitting EOF early is not an error. The
+/// returned length is less than `buf.len()` only if EOF is reached.
+pub(crate) fn read_full<R: Read + ?Sized>(reader: &mut R, buf: &mut [u8]) -> Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(err) if err.kind() == IoErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(filled)
+}
+
+/// Asynchronous version of [`read_full`].
+#[cfg(feature = "async")]
+pub(crate) async fn read_full_async<R>(reader: &mut R, buf: &mut [u8]) -> Result<usize>
+where
+    R: tokio::io::AsyncRead + Unpin + ?Sized,
+{
+    use tokio::io::AsyncReadExt;
+
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]).await {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(err) if err.kind() == IoErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(filled)
+}
+
+/// A reader that always stays `distance` bytes ahead of its consumer.
+///
+/// This is used to process a file in place when the output is longer
+/// than the input, so that the writer never overwrites unread bytes.
+pub(crate) struct Lookahead<R> {
+    inner: R,
+    /// Bytes read ahead, which are `distance` bytes between reads unless
+    /// the inner reader reaches EOF.
+    pending: Vec<u8>,
+    distance: usize,
+    eof: bool,
+}
+
+impl<R> Lookahead<R> {
+    pub fn new(inner: R, distance: usize) -> Self {
+        Self {
+            inner,
+            pending: Vec::with_capacity(distance),
+            distance,
+            eof: false,
+        }
+    }
+}
+
+impl<R: Read> Lookahead<R> {
+    /// Reads the first `distance` bytes ahead of time, so that the
+    /// consumer may write that many bytes before reading anything.
+    pub fn prefetch(&mut self) -> Result<()> {
+        if !self.eof && self.pending.len() < self.distance {
+            let wanted = self.distance - self.pending.len();
+            self.extend(wanted)?;
+        }
+        Ok(())
+    }
+
+    /// Reads up to `len` more bytes into `pending`.
+    fn extend(&mut self, len: usize) -> Result<()> {
+        let filled = self.pending.len();
+        self.pending.resize(filled + len, 0);
+        let rd_len = read_full(&mut self.inner, &mut self.pending[filled..]);
+        let rd_len = rd_len.inspect_err(|_| self.pending.truncate(filled))?;
+        self.pending.truncate(filled + rd_len);
+        self.eof = rd_len < len;
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for Lookahead<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        self.prefetch()?;
+        let distance = self.distance;
+        if !self.eof && buf.len() > distance {
+            // Read straight into `buf` after the pending bytes, and only
+            // hold back its last `distance` bytes.
+            buf[..distance].copy_from_slice(&self.pending);
+            let rd_len = self.inner.read(&mut buf[distance..])?;
+            if rd_len == 0 {
+                self.eof = true;
+                self.pending.clear();
+                return Ok(distance);
+            }
+            self.pending
+                .copy_from_slice(&buf[rd_len..rd_len + distance]);
+            return Ok(rd_len);
+        }
+
+        if !self.eof {
+            self.extend(buf.len())?;
+        }
+        let available = if self.eof {
+            self.pending.len()
+        } else {
+            self.pending.len() - distance
+        };
+        let rd_len = available.min(buf.len());
+        buf[..rd_len].copy_from_slice(&self.pending[..rd_len]);
+        self.pending.drain(..rd_len);
+        Ok(rd_len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::test_utils::payload;
+
+    /// Counts the bytes read from the inner reader.
+    struct Counted<'a> {
+        inner: Cursor<&'a [u8]>,
+        read: usize,
+    }
+
+    impl Read for Counted<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+            let rd_len = self.inner.read(buf)?;
+            self.read += rd_len;
+            Ok(rd_len)
+        }
+    }
+
+    #[test]
+    fn lookahead() {
+        let data = payload();
+        for distance in [0, 1, 32] {
+            for read_len in [1, 7, 32, 33, 4096] {
+                let inner = Counted {
+                    inner: Cursor::new(&data),
+                    read: 0,
+                };
+                let mut rd = Lookahead::new(inner, distance);
+                rd.prefetch().expect("failed to prefetch");
+                assert_eq!(rd.inner.read, distance);
+
+                let mut output = vec![];
+                let mut buf = vec![0; read_len];
+                loop {
+                    let len = rd.read(&mut buf).expect("failed to read");
+                    if len == 0 {
+                        break;
+                    }
+                    output.extend_from_slice(&buf[..len]);
+                    // The consumer never catches up with the inner reader.
+                    assert!(
+                        rd.inner.read == data.len() || rd.inner.read >= output.len() + distance
+                    );
+                }
+                assert_eq!(output, data);
+            }
+        }
+    }
+}
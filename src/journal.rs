@@ -0,0 +1,181 @@
+//! Journal for crash-safe in-place processing.
+//!
+//! When a file is processed in place with
+//! [`Pipeline::process_file_journaled`], a journal is kept in a sidecar
+//! file next to it (see [`journal_path`]). The file is processed segment
+//! by segment, and before a segment is overwritten, the journal records:
+//!
+//! - the offset of the segment,
+//! - a [`CipherSnapshot`] of the cipher at that offset,
+//! - the original bytes of the segment.
+//!
+//! The segment is synced to the disk before the journal moves on to the
+//! next one, and the journal is removed once the whole file is done. So
+//! if the process is killed, the journal tells exactly where it stopped,
+//! and the interrupted run can be finished with [`Pipeline::resume_file`]
+//! or undone with [`Pipeline::rollback_file`].
+//!
+//! # Security
+//!
+//! The journal is not encrypted. The snapshot is the state of the cipher,
+//! which is all it takes to decrypt the rest of the file, and for the
+//! seekable ciphers it is the initial key itself. When encrypting, the
+//! original bytes are plaintext too. So an interrupted run leaves the key
+//! next to the file until it's resumed or rolled back. The journal is
+//! created readable by its owner only on Unix, but it should still be
+//! treated as a secret and never be left behind.
+//!
+//! [`Pipeline::process_file_journaled`]: crate::pipeline::Pipeline::process_file_journaled
+//! [`Pipeline::resume_file`]: crate::pipeline::Pipeline::resume_file
+//! [`Pipeline::rollback_file`]: crate::pipeline::Pipeline::rollback_file
+
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::cipher::{CipherSnapshot, SNAPSHOT_LEN};
+use crate::error::{Error, Result};
+
+/// Extension appended to the file name to get the journal path.
+pub const JOURNAL_EXTENSION: &str = ".yafo-journal";
+
+/// Magic bytes at the beginning of every journal.
+const MAGIC: [u8; 8] = *b"YAFOJRNL";
+
+/// The latest journal format version.
+const JOURNAL_VERSION: u8 = 1;
+
+/// Length of the journal before the original bytes of the segment.
+const ENTRY_HEADER_LEN: usize = 40 + SNAPSHOT_LEN;
+
+/// Returns the path of the journal for the file at the given path.
+pub fn journal_path<P: AsRef<Path>>(path: P) -> PathBuf {
+    let mut journal = OsString::from(path.as_ref());
+    journal.push(JOURNAL_EXTENSION);
+    PathBuf::from(journal)
+}
+
+/// The state of an interrupted run recorded in a journal.
+pub(crate) struct Journal {
+    /// Offset of the segment being processed.
+    pub offset: u64,
+    /// Offset where the run stops.
+    pub end: u64,
+    /// Snapshot of the cipher at `offset`.
+    pub snapshot: CipherSnapshot,
+    /// Original bytes of the segment.
+    pub original: Vec<u8>,
+}
+
+impl Journal {
+    /// Atomically replaces the journal at the given path.
+    ///
+    /// The journal is synced to the disk before this returns.
+    pub fn write(
+        path: &Path,
+        offset: u64,
+        end: u64,
+        snapshot: &CipherSnapshot,
+        original: &[u8],
+    ) -> Result<()> {
+        let mut bytes = Vec::with_capacity(ENTRY_HEADER_LEN + original.len());
+        bytes.extend_from_slice(&MAGIC);
+        bytes.push(JOURNAL_VERSION);
+        bytes.extend_from_slice(&[0; 7]);
+        bytes.extend_from_slice(&offset.to_le_bytes());
+        bytes.extend_from_slice(&end.to_le_bytes());
+        bytes.extend_from_slice(&(original.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(&snapshot.to_bytes());
+        bytes.extend_from_slice(original);
+
+        let mut temp_path = OsString::from(path);
+        temp_path.push(".tmp");
+        let mut options = File::options();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut temp = options.open(&temp_path)?;
+        temp.write_all(&bytes)?;
+        temp.sync_all()?;
+        fs::rename(&temp_path, path)?;
+        Ok(())
+    }
+
+    /// Reads the journal at the given path.
+    pub fn read(path: &Path) -> Result<Journal> {
+        let mut bytes = vec![];
+        File::open(path)?.read_to_end(&mut bytes)?;
+
+        if bytes.len() < ENTRY_HEADER_LEN || bytes[0..8] != MAGIC {
+            return Err(Error::InvalidFormat("not a yafo journal".into()));
+        }
+        if bytes[8] != JOURNAL_VERSION {
+            return Err(Error::UnsupportedVersion(bytes[8]));
+        }
+
+        let read_u64 =
+            |offset: usize| u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap());
+        let offset = read_u64(16);
+        let end = read_u64(24);
+        let original_len = read_u64(32);
+        let snapshot = CipherSnapshot::from_bytes(bytes[40..ENTRY_HEADER_LEN].try_into().unwrap());
+        if original_len != (bytes.len() - ENTRY_HEADER_LEN) as u64
+            || offset
+                .checked_add(original_len)
+                .is_none_or(|seg_end| seg_end > end)
+        {
+            return Err(Error::InvalidFormat("corrupted journal".into()));
+        }
+
+        Ok(Journal {
+            offset,
+            end,
+            snapshot,
+            original: bytes.split_off(ENTRY_HEADER_LEN),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use super::{journal_path, Journal};
+    use crate::test_utils::temp_file_path;
+    use crate::types::DataChunk;
+    use crate::{EncryptState, KeyInit, ResumableCipher};
+
+    #[test]
+    fn round_trip() {
+        let path = temp_file_path("journal");
+        assert_eq!(
+            journal_path(Path::new("/tmp/a.txt")),
+            Path::new("/tmp/a.txt.yafo-journal")
+        );
+
+        let snapshot = EncryptState::with_key(DataChunk::from([1, 2, 3, 4, 5, 6, 7, 8])).snapshot();
+        Journal::write(&path, 16, 100, &snapshot, b"original").expect("failed to write journal");
+        let journal = Journal::read(&path).expect("failed to read journal");
+        assert_eq!(journal.offset, 16);
+        assert_eq!(journal.end, 100);
+        assert_eq!(journal.snapshot, snapshot);
+        assert_eq!(journal.original, b"original");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path)
+                .expect("failed to read metadata")
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        let mut bytes = fs::read(&path).expect("failed to read file");
+        bytes.pop();
+        fs::write(&path, &bytes).expect("failed to write file");
+        assert!(Journal::read(&path).is_err());
+        fs::remove_file(&path).expect("failed to remove file");
+    }
+}
@@ -1,13 +1,122 @@
 //! Types for initial key derivation.
 //!
-//! See [`KeyInit`] for details.
+//! See [`KeyInit`] for details. The way a seed phrase is turned into the
+//! initial key is configured by [`KeyDerivation`]. To tell whether a key
+//! is the right one without decrypting anything, see [`KeyCheck`].
+
+use std::io::Error as IoError;
 
 use sha1::{Digest, Sha1};
+use sha2::Sha256;
 
+use crate::error::{Error, Result};
 use crate::types::DataChunk;
 
 const MASK_BITS: [u8; 8] = [1, 2, 4, 8, 16, 32, 64, 128];
 
+const KEY_CHECK_CONTEXT: &[u8] = b"yafo key check";
+const RAW_KEY_CONTEXT: &[u8] = b"yafo raw key";
+
+/// Length of the key check value in bytes.
+pub const KEY_CHECK_LEN: usize = 4;
+
+/// Length of the salt used by salted key derivations in bytes.
+pub const SALT_LEN: usize = 16;
+
+/// Default number of iterations for [`KeyDerivation::Pbkdf2`].
+pub const DEFAULT_PBKDF2_ITERATIONS: u32 = 100_000;
+
+/// Algorithms to derive the initial key from a seed phrase.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum KeyDerivation {
+    /// The unsalted derivation described in `docs/algorithm-design.md`.
+    ///
+    /// Identical phrases always yield identical keys. It's kept for files
+    /// that are encrypted without derivation parameters.
+    V1,
+    /// PBKDF2-HMAC-SHA256 with a salt and a tunable iteration count.
+    Pbkdf2 {
+        salt: [u8; SALT_LEN],
+        iterations: u32,
+    },
+}
+
+impl KeyDerivation {
+    /// Identifier of [`KeyDerivation::V1`].
+    pub const V1_ID: u8 = 1;
+    /// Identifier of [`KeyDerivation::Pbkdf2`].
+    pub const PBKDF2_ID: u8 = 2;
+
+    /// Creates a PBKDF2 derivation with a random salt and the default
+    /// iteration count.
+    pub fn new_salted() -> Result<Self> {
+        Self::new_salted_with_iterations(DEFAULT_PBKDF2_ITERATIONS)
+    }
+
+    /// Creates a PBKDF2 derivation with a random salt and the given
+    /// iteration count, which must be non-zero.
+    pub fn new_salted_with_iterations(iterations: u32) -> Result<Self> {
+        if iterations == 0 {
+            return Err(Error::InvalidInput("iterations must be non-zero"));
+        }
+
+        let mut salt = [0; SALT_LEN];
+        getrandom::getrandom(&mut salt).map_err(IoError::from)?;
+        Ok(Self::Pbkdf2 { salt, iterations })
+    }
+
+    /// Returns the stable identifier of the derivation algorithm.
+    pub fn id(&self) -> u8 {
+        match self {
+            Self::V1 => Self::V1_ID,
+            Self::Pbkdf2 { .. } => Self::PBKDF2_ID,
+        }
+    }
+
+    /// Derives the initial key from the given seed phrase.
+    pub fn derive_key(&self, phrase: &str) -> DataChunk {
+        match self {
+            Self::V1 => {
+                let mut hasher = Sha1::new();
+
+                hasher.update(phrase.as_bytes());
+                let digest = hasher.finalize();
+
+                let mut seed_chunk = <[u8; 8]>::try_from(&digest.as_slice()[0..8])
+                    .expect("the slice can form an array");
+                for (byte, mask) in seed_chunk.iter_mut().zip(MASK_BITS.iter().cloned()) {
+                    *byte ^= mask;
+                }
+
+                DataChunk::from(seed_chunk)
+            }
+            Self::Pbkdf2 { salt, iterations } => pbkdf2_key(phrase.as_bytes(), salt, *iterations),
+        }
+    }
+
+    /// Derives the initial key from a raw key.
+    ///
+    /// The unsalted derivation uses the raw key as is. A salted one binds
+    /// it to the salt, so that every container gets its own key, even if
+    /// the same raw key is given.
+    pub fn derive_key_from_raw(&self, raw_key: DataChunk) -> DataChunk {
+        match self {
+            Self::V1 => raw_key,
+            Self::Pbkdf2 { salt, iterations } => {
+                // The context keeps raw keys apart from phrases of the same bytes.
+                let password = [RAW_KEY_CONTEXT, raw_key.as_ref()].concat();
+                pbkdf2_key(&password, salt, *iterations)
+            }
+        }
+    }
+}
+
+fn pbkdf2_key(password: &[u8], salt: &[u8; SALT_LEN], iterations: u32) -> DataChunk {
+    let mut key = DataChunk::default();
+    pbkdf2::pbkdf2_hmac::<Sha256>(password, salt, iterations, key.as_mut_slice());
+    key
+}
+
 /// Types which can be initialized from key.
 pub trait KeyInit: Sized {
     /// Creates new value with the given data chunk as key.
@@ -21,18 +130,224 @@ pub trait KeyInit: Sized {
     }
 
     /// Creates new value with the key derived from the given seed phrase.
+    ///
+    /// This uses the unsalted [`KeyDerivation::V1`], prefer
+    /// [`KeyInit::with_derived_key`] for new data.
     fn with_seed_phrase(phrase: &str) -> Self {
-        let mut hasher = Sha1::new();
+        Self::with_derived_key(phrase, &KeyDerivation::V1)
+    }
+
+    /// Creates new value with the key derived from the given seed phrase
+    /// by the given derivation.
+    fn with_derived_key(phrase: &str, derivation: &KeyDerivation) -> Self {
+        Self::with_key(derivation.derive_key(phrase))
+    }
 
-        hasher.update(phrase.as_bytes());
+    /// Creates new value with the given encoded initial key, which is
+    /// used as is.
+    ///
+    /// The key is either 16 hex digits, or 8 bytes in base64 (standard or
+    /// URL-safe alphabet, padding is optional). Fails with
+    /// [`Error::InvalidInput`] if it's neither.
+    ///
+    /// [`Error::InvalidInput`]: crate::error::Error::InvalidInput
+    fn with_raw_key(encoded: &str) -> Result<Self> {
+        parse_raw_key(encoded).map(Self::with_key)
+    }
+}
+
+/// Decodes a raw key in hex or base64.
+fn parse_raw_key(encoded: &str) -> Result<DataChunk> {
+    let encoded = encoded.trim();
+    let mut key = DataChunk::default();
+    match encoded.len() {
+        16 => {
+            // `from_str_radix` would accept a sign as well.
+            if !encoded.bytes().all(|c| c.is_ascii_hexdigit()) {
+                return Err(Error::InvalidInput("invalid hex digit in the raw key"));
+            }
+            for (byte, digits) in key
+                .as_mut_slice()
+                .iter_mut()
+                .zip(encoded.as_bytes().chunks(2))
+            {
+                let digits = std::str::from_utf8(digits).expect("the digits are ASCII");
+                *byte = u8::from_str_radix(digits, 16).expect("the digits are valid");
+            }
+        }
+        11 | 12 => {
+            let encoded = encoded.strip_suffix('=').unwrap_or(encoded);
+            if encoded.len() != 11 {
+                return Err(Error::InvalidInput("invalid base64 padding in the raw key"));
+            }
+            // 11 characters carry 66 bits, the trailing 2 bits must be zero.
+            let mut bits = 0u128;
+            for c in encoded.bytes() {
+                bits = bits << 6 | base64_value(c)? as u128;
+            }
+            if bits & 0b11 != 0 {
+                return Err(Error::InvalidInput("invalid base64 padding in the raw key"));
+            }
+            *key.as_mut_slice() = ((bits >> 2) as u64).to_be_bytes();
+        }
+        _ => {
+            return Err(Error::InvalidInput(
+                "the raw key must be 16 hex digits or 8 bytes in base64",
+            ))
+        }
+    }
+    Ok(key)
+}
+
+fn base64_value(c: u8) -> Result<u8> {
+    match c {
+        b'A'..=b'Z' => Ok(c - b'A'),
+        b'a'..=b'z' => Ok(c - b'a' + 26),
+        b'0'..=b'9' => Ok(c - b'0' + 52),
+        b'+' | b'-' => Ok(62),
+        b'/' | b'_' => Ok(63),
+        _ => Err(Error::InvalidInput(
+            "invalid base64 character in the raw key",
+        )),
+    }
+}
+
+/// The initial key itself can be derived, which is useful for working
+/// with the key directly (e.g. computing a key check value).
+impl KeyInit for DataChunk {
+    fn with_key(key: DataChunk) -> Self {
+        key
+    }
+}
+
+/// A short fingerprint of an initial key.
+///
+/// The value is hashed from the key with a dedicated context, so storing
+/// it alongside the ciphertext doesn't reveal the key itself. It's short
+/// on purpose: it can tell a mistyped phrase apart, but collisions are
+/// possible and it's not a proof of the key.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub struct KeyCheck([u8; KEY_CHECK_LEN]);
+
+impl KeyCheck {
+    /// Returns `true` if the given initial key matches this value.
+    pub fn verify(&self, key: &DataChunk) -> bool {
+        let expected = Self::with_key(*key);
+        // Compare all bytes regardless of where the first mismatch is.
+        self.0
+            .iter()
+            .zip(expected.0.iter())
+            .fold(0, |acc, (a, b)| acc | (a ^ b))
+            == 0
+    }
+
+    /// Extracts the bytes of the value.
+    pub fn as_bytes(&self) -> &[u8; KEY_CHECK_LEN] {
+        &self.0
+    }
+}
+
+impl From<[u8; KEY_CHECK_LEN]> for KeyCheck {
+    fn from(value: [u8; KEY_CHECK_LEN]) -> Self {
+        Self(value)
+    }
+}
+
+impl KeyInit for KeyCheck {
+    fn with_key(key: DataChunk) -> Self {
+        let mut hasher = Sha1::new();
+        hasher.update(KEY_CHECK_CONTEXT);
+        hasher.update(key.as_ref());
         let digest = hasher.finalize();
 
-        let mut seed_chunk =
-            <[u8; 8]>::try_from(&digest.as_slice()[0..8]).expect("the slice can form an array");
-        for (byte, mask) in seed_chunk.iter_mut().zip(MASK_BITS.iter().cloned()) {
-            *byte = *byte ^ mask;
+        let check = <[u8; KEY_CHECK_LEN]>::try_from(&digest.as_slice()[0..KEY_CHECK_LEN])
+            .expect("the slice can form an array");
+        Self(check)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::assert_matches::assert_matches;
+
+    use super::{KeyCheck, KeyDerivation, KeyInit};
+    use crate::error::Error;
+    use crate::types::DataChunk;
+
+    #[test]
+    fn key_derivation() {
+        let phrase = "you can not see me";
+        assert_eq!(
+            DataChunk::with_seed_phrase(phrase),
+            DataChunk::with_derived_key(phrase, &KeyDerivation::V1)
+        );
+
+        let derivation = KeyDerivation::Pbkdf2 {
+            salt: [42; 16],
+            iterations: 16,
+        };
+        let key = DataChunk::with_derived_key(phrase, &derivation);
+        assert_eq!(key, derivation.derive_key(phrase));
+        assert_ne!(key, DataChunk::with_seed_phrase(phrase));
+
+        let other_salt = KeyDerivation::Pbkdf2 {
+            salt: [43; 16],
+            iterations: 16,
+        };
+        assert_ne!(key, other_salt.derive_key(phrase));
+
+        let random_salt_1 = KeyDerivation::new_salted_with_iterations(16).unwrap();
+        let random_salt_2 = KeyDerivation::new_salted_with_iterations(16).unwrap();
+        assert_ne!(random_salt_1, random_salt_2);
+
+        let raw_key = DataChunk::from(*b"12345678");
+        assert_eq!(KeyDerivation::V1.derive_key_from_raw(raw_key), raw_key);
+        let salted = derivation.derive_key_from_raw(raw_key);
+        assert_ne!(salted, raw_key);
+        assert_ne!(salted, derivation.derive_key("12345678"));
+        assert_ne!(salted, other_salt.derive_key_from_raw(raw_key));
+        assert_matches!(
+            KeyDerivation::new_salted_with_iterations(0),
+            Err(Error::InvalidInput(_))
+        );
+    }
+
+    #[test]
+    fn key_check() {
+        let key = DataChunk::with_seed_phrase("you can not see me");
+        let check = KeyCheck::with_seed_phrase("you can not see me");
+
+        assert_eq!(check, KeyCheck::with_key(key));
+        assert_ne!(&check.as_bytes()[..], &key.as_slice()[0..4]);
+        assert!(check.verify(&key));
+        assert!(!check.verify(&DataChunk::with_seed_phrase("you can see me")));
+    }
+
+    #[test]
+    fn raw_key() {
+        let key = DataChunk::from([0xfb, 0xef, 0x01, 0x23, 0x45, 0x67, 0x89, 0xff]);
+        for encoded in [
+            "fbef0123456789ff",
+            "FBEF0123456789FF",
+            "++8BI0Vnif8=",
+            "--8BI0Vnif8",
+            " ++8BI0Vnif8=\n",
+        ] {
+            let parsed = DataChunk::with_raw_key(encoded);
+            assert_eq!(parsed.ok(), Some(key), "failed to parse {:?}", encoded);
         }
 
-        Self::with_key(DataChunk::from(seed_chunk))
+        for encoded in [
+            "",
+            "fbef0123456789f",
+            "fbef0123456789fg",
+            "+fbef0123456789f",
+            "++8BI0Vnif9=",
+            "++8BI0Vnif8==",
+            "++8BI0Vn*f8=",
+        ] {
+            let err = DataChunk::with_raw_key(encoded).unwrap_err();
+            assert_matches!(err, Error::InvalidInput(_), "accepted {:?}", encoded);
+        }
     }
 }
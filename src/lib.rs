@@ -16,21 +16,44 @@
 //! from the programs developed by languages other than Rust. To do so, build
 //! the package with `ffi` feature.
 //!
-//! See the documentation of [pipeline] module for the entry point.
+//! See the documentation of [pipeline] module for the entry point. To
+//! plug yafo into an existing I/O stack, see the [stream] module. With the
+//! `async` feature, the [`async_stream`] module provides the same for tokio.
 
+pub mod algorithm;
+#[cfg(feature = "async")]
+#[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+pub mod async_stream;
 pub mod cipher;
+pub mod error;
 #[cfg(feature = "ffi")]
 #[cfg_attr(docsrs, doc(cfg(feature = "ffi")))]
 pub mod ffi;
+pub mod header;
+pub mod integrity;
+pub mod journal;
 pub mod key_init;
 pub mod pipeline;
+pub mod stream;
 pub mod types;
 
 mod io;
+#[cfg(test)]
+mod test_utils;
 
-pub use cipher::{Cipher, DecryptState, EncryptState};
-pub use key_init::KeyInit;
+pub use algorithm::Algorithm;
+#[cfg(feature = "strong")]
+#[cfg_attr(docsrs, doc(cfg(feature = "strong")))]
+pub use cipher::ChaCha20State;
+pub use cipher::{
+    Cipher, CipherSnapshot, DecryptState, EncryptState, ResumableCipher, SeekableCipher,
+    SeekableDecryptState, SeekableEncryptState,
+};
+pub use error::{Error, Result};
+pub use header::Header;
+pub use key_init::{KeyCheck, KeyDerivation, KeyInit};
 pub use pipeline::Pipeline;
+pub use stream::{CipherReader, CipherWriter};
 
 #[cfg(test)]
 mod tests {
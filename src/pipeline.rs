@@ -11,6 +11,8 @@
 //! To encrypt an in-memory data buffer:
 //!
 //! ```rust
+//! # use std::io::Cursor;
+//! # use yafo::{EncryptState, KeyInit, Pipeline};
 //! let plain_text = "hello";
 //! let seed_phrase = "you can not see me";
 //!
@@ -22,26 +24,103 @@
 //!
 //! // Create the pipeline and encrypt the data.
 //! let pipeline = Pipeline::new();
-//! pipeline.process(input, &mut output, encrypt, None);
+//! pipeline.process(input, &mut output, encrypt, None).unwrap();
 //! ```
+//!
+//! Files can also be processed in place with multiple threads, see
+//! [`Pipeline::process_file_parallel`].
+//!
+//! By default, the output carries no metadata at all. To produce a
+//! self-describing container, attach a [`Header`] with
+//! [`Pipeline::with_header`] when encrypting, and use
+//! [`Pipeline::strip_header`] when decrypting.
 
-use std::io::{BufReader, BufWriter, Read, Result, Write};
-use std::path::Path;
+use std::ffi::OsString;
+use std::fs::{self, File, Permissions};
+use std::io::{self, BufReader, BufWriter, ErrorKind as IoErrorKind, Read, Seek, SeekFrom, Write};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
 
-use crate::io::file::duplex_file;
+use crate::cipher::{ResumableCipher, SeekableCipher};
+use crate::error::{Error, Result};
+use crate::header::{Header, FLAG_TAGGED, HEADER_LEN};
+use crate::integrity::{Mac, TagReader, TagWriter, TAG_LEN};
+use crate::io::file::{duplex_file, sync_parent_dir};
+#[cfg(feature = "async")]
+use crate::io::read_full_async;
+use crate::io::{read_full, Lookahead};
+use crate::journal::{journal_path, Journal};
 use crate::types::{DataChunk, CHUNK_SIZE};
 use crate::Cipher;
 
+/// Number of bytes processed in a batch, must be a multiple of
+/// [`CHUNK_SIZE`].
+const BLOCK_SIZE: usize = CHUNK_SIZE * 8192;
+
+/// Number of bytes recorded in a journal entry, must be a multiple of
+/// [`CHUNK_SIZE`].
+const JOURNAL_SEGMENT_SIZE: usize = BLOCK_SIZE * 16;
+
+/// Capacity of the buffers used by [`Pipeline::with_buffer`].
+pub const DEFAULT_BUFFER_CAPACITY: usize = 1024 * 1024 * 4;
+
 /// Trait implemented by progress reporters for receiving
 /// the statistics while processing the data.
+///
+/// A run starts with [`ProgressReporter::started`], and ends with exactly
+/// one of [`ProgressReporter::finished`], [`ProgressReporter::failed`] and
+/// [`ProgressReporter::cancelled`]. If a run fails before it starts (e.g.
+/// the file cannot be opened), the error is returned without any event.
 pub trait ProgressReporter {
+    /// Called once before any bytes are processed.
+    ///
+    /// An optional total size is presented when available.
+    fn started(&mut self, _total: Option<u64>) {}
+
     /// Called when bytes of the given length are processed.
     ///
     /// Argument `n` is the length of bytes processed in this round.
     /// Note that it's not the length of total processed bytes.
-    ///
-    /// An optional total size is presented when available.
-    fn bytes_processed(&mut self, n: usize, total: Option<usize>);
+    fn bytes_processed(&mut self, n: u64);
+
+    /// Called once when all the bytes are processed successfully.
+    fn finished(&mut self, _summary: &Summary) {}
+
+    /// Called once when the processing fails with the given error.
+    fn failed(&mut self, _error: &Error, _summary: &Summary) {}
+
+    /// Called once when the processing is cancelled.
+    fn cancelled(&mut self, _summary: &Summary) {}
+}
+
+impl<R> ProgressReporter for &mut R
+where
+    R: ProgressReporter + ?Sized,
+{
+    fn started(&mut self, total: Option<u64>) {
+        (*self).started(total)
+    }
+
+    fn bytes_processed(&mut self, n: u64) {
+        (*self).bytes_processed(n)
+    }
+
+    fn finished(&mut self, summary: &Summary) {
+        (*self).finished(summary)
+    }
+
+    fn failed(&mut self, error: &Error, summary: &Summary) {
+        (*self).failed(error, summary)
+    }
+
+    fn cancelled(&mut self, summary: &Summary) {
+        (*self).cancelled(summary)
+    }
 }
 
 /// A no-op [`ProgressReporter`] as a placeholder type.
@@ -49,12 +128,156 @@ pub trait ProgressReporter {
 pub struct NopReporter;
 
 impl ProgressReporter for NopReporter {
-    fn bytes_processed(&mut self, _n: usize, _total: Option<usize>) {}
+    fn bytes_processed(&mut self, _n: u64) {}
+}
+
+/// Statistics of a run, reported when it ends.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Summary {
+    /// Number of bytes processed in this run.
+    pub bytes_processed: u64,
+    /// Time elapsed since the run started.
+    pub elapsed: Duration,
+}
+
+impl Summary {
+    /// Returns the average throughput in bytes per second.
+    pub fn throughput(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs > 0.0 {
+            self.bytes_processed as f64 / secs
+        } else {
+            0.0
+        }
+    }
+}
+
+/// A handle to cancel the processing of a [`Pipeline`], which can be
+/// shared with other threads.
+///
+/// The pipeline checks the token between blocks, so it stops shortly
+/// after [`CancellationToken::cancel`] is called and fails with
+/// [`Error::Cancelled`].
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    /// Creates a new token that is not cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requests the pipelines using this token to stop.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    /// Returns `true` if the token is cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// Tracks a run and reports its events to a [`ProgressReporter`].
+struct Progress<R> {
+    reporter: R,
+    cancellation: Option<CancellationToken>,
+    bytes_processed: u64,
+    started_at: Instant,
+}
+
+impl<R: ProgressReporter> Progress<R> {
+    fn start(mut reporter: R, cancellation: Option<CancellationToken>, total: Option<u64>) -> Self {
+        reporter.started(total);
+        Self {
+            reporter,
+            cancellation,
+            bytes_processed: 0,
+            started_at: Instant::now(),
+        }
+    }
+
+    /// Fails with [`Error::Cancelled`] if the run is cancelled.
+    fn check_cancelled(&self) -> Result<()> {
+        check_cancelled(self.cancellation.as_ref(), self.bytes_processed)
+    }
+
+    fn advance(&mut self, n: usize) {
+        self.bytes_processed += n as u64;
+        self.reporter.bytes_processed(n as u64);
+    }
+
+    /// Reports bytes processed by an earlier run, which are not counted
+    /// in the summary.
+    fn skip(&mut self, n: u64) {
+        self.reporter.bytes_processed(n);
+    }
+
+    /// Reports the end of the run with its result.
+    fn finish(mut self, result: Result<()>) -> Result<()> {
+        let summary = Summary {
+            bytes_processed: self.bytes_processed,
+            elapsed: self.started_at.elapsed(),
+        };
+        match &result {
+            Ok(()) => self.reporter.finished(&summary),
+            Err(Error::Cancelled { .. }) => self.reporter.cancelled(&summary),
+            Err(err) => self.reporter.failed(err, &summary),
+        }
+        result
+    }
+}
+
+/// Returns the total length to report for an input of the given length,
+/// which excludes the tag that is stripped when verifying it.
+fn reported_total(total_len: Option<u64>, tag: TagMode) -> Option<u64> {
+    match tag {
+        TagMode::Verify(_) => total_len.map(|len| len.saturating_sub(TAG_LEN as u64)),
+        _ => total_len,
+    }
+}
+
+/// Fails with [`Error::InvalidInput`] if the cipher can't process `len`
+/// more bytes.
+fn check_remaining_len<C: Cipher + ?Sized>(cipher: &C, len: u64) -> Result<()> {
+    match cipher.remaining_len() {
+        Some(remaining) if len > remaining => {
+            Err(Error::InvalidInput("the input is too long for the cipher"))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// How the container header is handled by a [`Pipeline`].
+#[derive(Clone, Copy)]
+enum HeaderMode {
+    /// The data is processed as is (raw mode).
+    None,
+    /// The header is written before the processed data.
+    Prepend(Header),
+    /// The header is read from the input and discarded.
+    Strip,
+}
+
+/// How the integrity tag is handled by a [`Pipeline`].
+#[derive(Clone, Copy)]
+enum TagMode {
+    /// No tag is involved.
+    None,
+    /// A tag computed with the given key is written after the data.
+    Append(DataChunk),
+    /// The tag after the data is checked with the given key.
+    Verify(DataChunk),
 }
 
 /// A configurable pipeline to process data with some specified cipher.
 pub struct Pipeline<R> {
-    buffered: bool,
+    read_buffer_capacity: usize,
+    write_buffer_capacity: usize,
+    header: HeaderMode,
+    tag: TagMode,
+    workers: Option<NonZeroUsize>,
+    cancellation: Option<CancellationToken>,
     progress_reporter: R,
 }
 
@@ -62,16 +285,128 @@ impl Pipeline<NopReporter> {
     /// Creates a new pipeline with default configurations.
     pub fn new() -> Pipeline<NopReporter> {
         Self {
-            buffered: false,
+            read_buffer_capacity: 0,
+            write_buffer_capacity: 0,
+            header: HeaderMode::None,
+            tag: TagMode::None,
+            workers: None,
+            cancellation: None,
             progress_reporter: NopReporter,
         }
     }
 }
 
+impl Default for Pipeline<NopReporter> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<R> Pipeline<R> {
     /// Returns a buffered version of the current pipeline.
-    pub fn with_buffer(mut self) -> Pipeline<R> {
-        self.buffered = true;
+    ///
+    /// Both the input and the output are buffered with
+    /// [`DEFAULT_BUFFER_CAPACITY`] bytes.
+    pub fn with_buffer(self) -> Pipeline<R> {
+        self.with_buffer_capacity(DEFAULT_BUFFER_CAPACITY)
+    }
+
+    /// Returns a version of the current pipeline that buffers both the
+    /// input and the output with the given capacity in bytes.
+    ///
+    /// A capacity of zero disables the buffering.
+    pub fn with_buffer_capacity(self, capacity: usize) -> Pipeline<R> {
+        self.with_read_buffer_capacity(capacity)
+            .with_write_buffer_capacity(capacity)
+    }
+
+    /// Returns a version of the current pipeline that buffers the input
+    /// with the given capacity in bytes.
+    ///
+    /// A capacity of zero disables the buffering.
+    pub fn with_read_buffer_capacity(mut self, capacity: usize) -> Pipeline<R> {
+        self.read_buffer_capacity = capacity;
+        self
+    }
+
+    /// Returns a version of the current pipeline that buffers the output
+    /// with the given capacity in bytes.
+    ///
+    /// A capacity of zero disables the buffering.
+    pub fn with_write_buffer_capacity(mut self, capacity: usize) -> Pipeline<R> {
+        self.write_buffer_capacity = capacity;
+        self
+    }
+
+    /// Returns a version of the current pipeline that writes the given
+    /// container header before the processed data.
+    pub fn with_header(mut self, header: Header) -> Pipeline<R> {
+        self.header = HeaderMode::Prepend(header);
+        self
+    }
+
+    /// Returns a version of the current pipeline that reads the container
+    /// header from the input and discards it before processing the data.
+    ///
+    /// Processing fails if the input does not start with a valid header.
+    /// Note that the header is not checked against the cipher, use
+    /// [`Header::read_from`] to inspect it beforehand.
+    pub fn strip_header(mut self) -> Pipeline<R> {
+        self.header = HeaderMode::Strip;
+        self
+    }
+
+    /// Returns a version of the current pipeline that writes an integrity
+    /// tag after the processed data, see the [`integrity`] module.
+    ///
+    /// The key should be the initial key of the cipher, and the tag is only
+    /// as strong as that 64-bit key. If a container header is written, it's
+    /// marked with [`FLAG_TAGGED`].
+    ///
+    /// [`integrity`]: crate::integrity
+    /// [`FLAG_TAGGED`]: crate::header::FLAG_TAGGED
+    pub fn with_tag(mut self, key: &DataChunk) -> Pipeline<R> {
+        self.tag = TagMode::Append(*key);
+        self
+    }
+
+    /// Returns a version of the current pipeline that checks the integrity
+    /// tag after the input data, and excludes it from the output.
+    ///
+    /// Processing fails with [`Error::TagMismatch`] if the tag doesn't
+    /// match. The output of streams is produced before the tag is read, so
+    /// it must be discarded on failure. Files are checked before being
+    /// processed, so they are left untouched on failure.
+    pub fn verify_tag(mut self, key: &DataChunk) -> Pipeline<R> {
+        self.tag = TagMode::Verify(*key);
+        self
+    }
+
+    /// Returns a version of the current pipeline that uses the given
+    /// number of threads for parallel processing.
+    ///
+    /// By default, the number of threads is the available parallelism
+    /// of the system. Zero is treated as one.
+    pub fn with_workers(mut self, workers: usize) -> Pipeline<R> {
+        self.workers = NonZeroUsize::new(workers.max(1));
+        self
+    }
+
+    /// Returns a version of the current pipeline that can be cancelled
+    /// with the given token.
+    ///
+    /// When processing a file in place, the bytes processed before the
+    /// cancellation are written back, so the first
+    /// [`Error::Cancelled::bytes_processed`] bytes of the input are
+    /// transformed and the rest are left untouched. This doesn't hold with
+    /// a container header, which shifts the data, or with parallel
+    /// processing, where the processed bytes are not contiguous. A
+    /// cancelled journaled run keeps its journal, so it can be resumed or
+    /// rolled back.
+    ///
+    /// [`Error::Cancelled::bytes_processed`]: crate::error::Error::Cancelled
+    pub fn with_cancellation_token(mut self, token: CancellationToken) -> Pipeline<R> {
+        self.cancellation = Some(token);
         self
     }
 
@@ -81,7 +416,12 @@ impl<R> Pipeline<R> {
         NR: ProgressReporter,
     {
         Pipeline {
-            buffered: self.buffered,
+            read_buffer_capacity: self.read_buffer_capacity,
+            write_buffer_capacity: self.write_buffer_capacity,
+            header: self.header,
+            tag: self.tag,
+            workers: self.workers,
+            cancellation: self.cancellation,
             progress_reporter: reporter,
         }
     }
@@ -93,78 +433,697 @@ where
 {
     /// Consumes the pipeline and processes the data by given
     /// input & output stream and cipher.
+    ///
+    /// The input is always consumed in whole chunks, regardless of how
+    /// many bytes each [`Read::read`] call returns. Only the last chunk
+    /// before EOF may be shorter than [`CHUNK_SIZE`].
     pub fn process<I: Read, O: Write, C: Cipher>(
         self,
         input: I,
         output: O,
         cipher: C,
-        total_len: Option<usize>,
+        total_len: Option<u64>,
     ) -> Result<()> {
-        struct WithProgress<W: Write, R: ProgressReporter> {
-            inner: W,
-            reporter: R,
-            total_len: Option<usize>,
-        }
+        let total_len = reported_total(total_len, self.tag);
+        let mut progress = Progress::start(self.progress_reporter, self.cancellation, total_len);
 
-        impl<W: Write, R: ProgressReporter> Write for WithProgress<W, R> {
-            fn write(&mut self, buf: &[u8]) -> Result<usize> {
-                let wr_len = self.inner.write(buf)?;
-                self.reporter.bytes_processed(wr_len, self.total_len);
-                Ok(wr_len)
-            }
+        // Buffers with zero capacity pass all reads and writes through.
+        let input = BufReader::with_capacity(self.read_buffer_capacity, input);
+        let output = BufWriter::with_capacity(self.write_buffer_capacity, output);
+        let result = process_inner(input, output, cipher, self.header, self.tag, &mut progress);
+        progress.finish(result)
+    }
 
-            fn flush(&mut self) -> Result<()> {
-                self.inner.flush()
+    /// Consumes the pipeline and processes the file at the given path
+    /// in place.
+    ///
+    /// The file is resized when a container header is written or stripped.
+    pub fn process_file<P, C>(self, path: P, cipher: C) -> Result<()>
+    where
+        P: AsRef<Path>,
+        C: Cipher,
+    {
+        let path = path.as_ref();
+        let (rd, mut wr) = duplex_file(path)?;
+        let total_len = reported_total(Some(rd.file_len()?), self.tag);
+        let mut progress = Progress::start(self.progress_reporter, self.cancellation, total_len);
+
+        // The file is checked before being touched.
+        let result = check_remaining_len(&cipher, total_len.unwrap_or_default());
+        let result = result.and_then(|()| match self.tag {
+            TagMode::Verify(key) => verify_file_tag(path, &key, self.read_buffer_capacity),
+            _ => Ok(()),
+        });
+        let result = result.and_then(|()| {
+            let output = BufWriter::with_capacity(self.write_buffer_capacity, &mut wr);
+            if let HeaderMode::Prepend(_) = self.header {
+                // Stay ahead of the writer, which is shifted by the header.
+                // The header is written before anything is read, so it must
+                // be read ahead of time when the output is not buffered.
+                let mut rd = Lookahead::new(rd, HEADER_LEN);
+                rd.prefetch()?;
+                let input = BufReader::with_capacity(self.read_buffer_capacity, rd);
+                process_inner(input, output, cipher, self.header, self.tag, &mut progress)
+            } else {
+                let input = BufReader::with_capacity(self.read_buffer_capacity, rd);
+                process_inner(input, output, cipher, self.header, self.tag, &mut progress)
             }
+        });
+        let result = result.and_then(|()| Ok(wr.truncate()?));
+        progress.finish(result)
+    }
+
+    /// Consumes the pipeline and processes the file at the given path in
+    /// place, keeping a journal so that an interrupted run can be resumed
+    /// or rolled back.
+    ///
+    /// See the [`journal`] module for how it works. This fails if a journal
+    /// of an interrupted run exists, which must be resolved by
+    /// [`Pipeline::resume_file`] or [`Pipeline::rollback_file`] first.
+    ///
+    /// Container headers and integrity tags are not supported, the
+    /// pipeline must be in the raw mode. The buffer capacities are not
+    /// used.
+    ///
+    /// **Warning:** the journal holds the cipher state and the original
+    /// bytes in plaintext, so the key is left next to the file until an
+    /// interrupted run is resumed or rolled back. See the [security notes]
+    /// of the journal.
+    ///
+    /// [`journal`]: crate::journal
+    /// [security notes]: crate::journal#security
+    pub fn process_file_journaled<P, C>(self, path: P, cipher: C) -> Result<()>
+    where
+        P: AsRef<Path>,
+        C: ResumableCipher,
+    {
+        let (mut file, journal) = self.open_journaled(path.as_ref())?;
+        if journal.exists() {
+            return Err(Error::InvalidInput(
+                "the journal of an interrupted run exists",
+            ));
         }
 
-        let output_with_progress = WithProgress {
-            inner: output,
-            reporter: self.progress_reporter,
-            total_len,
-        };
+        let file_len = file.metadata()?.len();
+        check_remaining_len(&cipher, file_len)?;
+        let mut progress =
+            Progress::start(self.progress_reporter, self.cancellation, Some(file_len));
+        let result = process_journaled(&mut file, &journal, 0, file_len, cipher, &mut progress);
+        progress.finish(result)
+    }
 
-        if self.buffered {
-            // TODO: make this tweakable for library users.
-            const BUF_SIZE: usize = 1024 * 1024 * 4;
-            let buf_input = BufReader::with_capacity(BUF_SIZE, input);
-            let buf_output = BufWriter::with_capacity(BUF_SIZE, output_with_progress);
-            Self::process_inner(buf_input, buf_output, cipher)
-        } else {
-            Self::process_inner(input, output_with_progress, cipher)
+    /// Consumes the pipeline and finishes the interrupted run recorded in
+    /// the journal of the file at the given path.
+    ///
+    /// The cipher must be of the same type and key as the interrupted
+    /// run, its state is restored from the journal.
+    pub fn resume_file<P, C>(self, path: P, mut cipher: C) -> Result<()>
+    where
+        P: AsRef<Path>,
+        C: ResumableCipher,
+    {
+        let (mut file, journal_path) = self.open_journaled(path.as_ref())?;
+        let journal = Journal::read(&journal_path)?;
+        let mut progress =
+            Progress::start(self.progress_reporter, self.cancellation, Some(journal.end));
+        progress.skip(journal.offset);
+
+        cipher.restore(&journal.snapshot);
+        let result = restore_segment(&mut file, &journal).and_then(|()| {
+            process_journaled(
+                &mut file,
+                &journal_path,
+                journal.offset,
+                journal.end,
+                cipher,
+                &mut progress,
+            )
+        });
+        progress.finish(result)
+    }
+
+    /// Consumes the pipeline and undoes the interrupted run recorded in
+    /// the journal of the file at the given path.
+    ///
+    /// The cipher must be the inverse of the interrupted run (e.g. a
+    /// [`DecryptState`] to undo an encryption) with the same key, in its
+    /// initial state. Rolling back is journaled as well, if it's also
+    /// interrupted, resume it with [`Pipeline::resume_file`] and the same
+    /// inverse cipher.
+    ///
+    /// [`DecryptState`]: crate::cipher::DecryptState
+    pub fn rollback_file<P, C>(self, path: P, cipher: C) -> Result<()>
+    where
+        P: AsRef<Path>,
+        C: ResumableCipher,
+    {
+        let (mut file, journal_path) = self.open_journaled(path.as_ref())?;
+        let journal = Journal::read(&journal_path)?;
+        let mut progress = Progress::start(
+            self.progress_reporter,
+            self.cancellation,
+            Some(journal.offset),
+        );
+
+        let result = restore_segment(&mut file, &journal).and_then(|()| {
+            process_journaled(
+                &mut file,
+                &journal_path,
+                0,
+                journal.offset,
+                cipher,
+                &mut progress,
+            )
+        });
+        progress.finish(result)
+    }
+
+    /// Opens the file for journaled processing, returning it with the
+    /// path of its journal.
+    fn open_journaled(&self, path: &Path) -> Result<(File, PathBuf)> {
+        if !matches!(self.header, HeaderMode::None) {
+            return Err(Error::InvalidInput(
+                "container headers are not supported by journaled processing",
+            ));
+        }
+        if !matches!(self.tag, TagMode::None) {
+            return Err(Error::InvalidInput(
+                "integrity tags are not supported by journaled processing",
+            ));
         }
+
+        let file = File::options().read(true).write(true).open(path)?;
+        Ok((file, journal_path(path)))
     }
 
-    pub fn process_file<P, C>(self, path: P, cipher: C) -> Result<()>
+    /// Consumes the pipeline and processes the file at path `src` into a
+    /// new file at path `dst`.
+    ///
+    /// The result is written to a temporary file next to `dst`, which gets
+    /// the permissions of `src`, and is synced and then renamed to `dst`
+    /// on success, replacing any existing file. The source file is never
+    /// modified, and `dst` is left untouched if processing fails.
+    pub fn process_file_to<P, Q, C>(self, src: P, dst: Q, cipher: C) -> Result<()>
     where
         P: AsRef<Path>,
+        Q: AsRef<Path>,
+        C: Cipher,
+    {
+        let input = File::open(src)?;
+        let metadata = input.metadata()?;
+        let total_len = Some(metadata.len());
+        self.write_file(
+            input,
+            dst.as_ref(),
+            cipher,
+            total_len,
+            Some(metadata.permissions()),
+        )
+    }
+
+    /// Consumes the pipeline and processes the data from the input stream
+    /// into a new file at path `dst`.
+    ///
+    /// Like [`Pipeline::process_file_to`], the result is written to a
+    /// temporary file that replaces `dst` on success, so `dst` is left
+    /// untouched if processing fails.
+    pub fn process_to_file<I, Q, C>(
+        self,
+        input: I,
+        dst: Q,
+        cipher: C,
+        total_len: Option<u64>,
+    ) -> Result<()>
+    where
+        I: Read,
+        Q: AsRef<Path>,
         C: Cipher,
     {
-        let (rd, wr) = duplex_file(path)?;
-        let file_len = rd.file_len()?;
-        self.process(rd, wr, cipher, Some(file_len as usize))
+        self.write_file(input, dst.as_ref(), cipher, total_len, None)
     }
 
-    fn process_inner<I: Read, O: Write, C: Cipher>(
-        mut input: I,
-        mut output: O,
-        mut cipher: C,
-    ) -> Result<()> {
-        let mut chunk = DataChunk::default();
-        loop {
-            // FIXME: handle the case where the read chunk is less than 8
-            // bytes before reaching EOF.
-            let rd_len = input.read(chunk.as_mut_slice())?;
-            if rd_len == 0 {
-                return Ok(());
-            } else if rd_len != CHUNK_SIZE {
-                chunk.as_mut_slice()[rd_len..CHUNK_SIZE].fill(0);
+    /// Processes the input into a temporary file with the given
+    /// permissions, and renames it to `dst` on success.
+    fn write_file<I, C>(
+        self,
+        input: I,
+        dst: &Path,
+        cipher: C,
+        total_len: Option<u64>,
+        permissions: Option<Permissions>,
+    ) -> Result<()>
+    where
+        I: Read,
+        C: Cipher,
+    {
+        let temp_path = temp_path_for(dst)?;
+        let temp = File::options()
+            .write(true)
+            .create_new(true)
+            .open(&temp_path)?;
+        let total_len = reported_total(total_len, self.tag);
+        let mut progress = Progress::start(self.progress_reporter, self.cancellation, total_len);
+
+        let result = permissions.map_or(Ok(()), |permissions| temp.set_permissions(permissions));
+        let result = result.map_err(Error::from).and_then(|()| {
+            let input = BufReader::with_capacity(self.read_buffer_capacity, input);
+            let output = BufWriter::with_capacity(self.write_buffer_capacity, &temp);
+            process_inner(input, output, cipher, self.header, self.tag, &mut progress)
+        });
+        let result = result.and_then(|()| {
+            temp.sync_all()?;
+            fs::rename(&temp_path, dst)?;
+            // The rename is only durable once the directory is synced.
+            sync_parent_dir(dst)?;
+            Ok(())
+        });
+        if result.is_err() {
+            let _ = fs::remove_file(&temp_path);
+        }
+        progress.finish(result)
+    }
+
+    /// Consumes the pipeline and processes the data from an asynchronous
+    /// input to an asynchronous output.
+    ///
+    /// This behaves the same as [`Pipeline::process`], including the
+    /// buffering and the container header, but never blocks the thread.
+    #[cfg(feature = "async")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+    pub async fn process_async<I, O, C>(
+        self,
+        input: I,
+        output: O,
+        cipher: C,
+        total_len: Option<u64>,
+    ) -> Result<()>
+    where
+        I: tokio::io::AsyncRead + Unpin,
+        O: tokio::io::AsyncWrite + Unpin,
+        C: Cipher,
+    {
+        use tokio::io::{BufReader as AsyncBufReader, BufWriter as AsyncBufWriter};
+
+        let total_len = reported_total(total_len, self.tag);
+        let mut progress = Progress::start(self.progress_reporter, self.cancellation, total_len);
+
+        // Tokio buffers require a non-zero capacity.
+        let input = AsyncBufReader::with_capacity(self.read_buffer_capacity.max(1), input);
+        let output = AsyncBufWriter::with_capacity(self.write_buffer_capacity.max(1), output);
+        let result =
+            process_inner_async(input, output, cipher, self.header, self.tag, &mut progress).await;
+        progress.finish(result)
+    }
+
+    /// Consumes the pipeline and processes the file at the given path
+    /// in place with multiple threads.
+    ///
+    /// The file is split into segments that are processed concurrently,
+    /// see [`Pipeline::with_workers`] for the number of threads. This
+    /// requires a [`SeekableCipher`], and its current chunk index is
+    /// regarded as the index of the first chunk of the file.
+    ///
+    /// Container headers and integrity tags are not supported, the
+    /// pipeline must be in the raw mode.
+    pub fn process_file_parallel<P, C>(self, path: P, cipher: C) -> Result<()>
+    where
+        P: AsRef<Path>,
+        C: SeekableCipher + Clone + Send,
+    {
+        if !matches!(self.header, HeaderMode::None) {
+            return Err(Error::InvalidInput(
+                "container headers are not supported by parallel processing",
+            ));
+        }
+        if !matches!(self.tag, TagMode::None) {
+            return Err(Error::InvalidInput(
+                "integrity tags are not supported by parallel processing",
+            ));
+        }
+
+        let path = path.as_ref();
+        let file_len = fs::metadata(path)?.len();
+        check_remaining_len(&cipher, file_len)?;
+        let workers = match self.workers {
+            Some(workers) => workers,
+            None => thread::available_parallelism()?,
+        };
+
+        // Segments must start at chunk boundaries, and it's not worth
+        // spawning a thread for less than a block.
+        let segment_len = file_len.div_ceil(workers.get() as u64);
+        let segment_len = segment_len
+            .next_multiple_of(CHUNK_SIZE as u64)
+            .max(BLOCK_SIZE as u64);
+        let first_chunk = cipher.chunk_index();
+
+        let mut progress =
+            Progress::start(self.progress_reporter, self.cancellation, Some(file_len));
+        let (progress_tx, progress_rx) = mpsc::channel();
+        let result = thread::scope(|scope| {
+            let workers: Vec<_> = (0..file_len)
+                .step_by(segment_len as usize)
+                .map(|start| {
+                    let end = (start + segment_len).min(file_len);
+                    let mut cipher = cipher.clone();
+                    let seeked = cipher.seek_chunk(first_chunk + start / CHUNK_SIZE as u64);
+                    let progress_tx = progress_tx.clone();
+                    let cancellation = progress.cancellation.clone();
+                    scope.spawn(move || {
+                        seeked?;
+                        process_segment(path, start, end, cipher, cancellation, progress_tx)
+                    })
+                })
+                .collect();
+
+            // The channel is closed once all workers exit.
+            drop(progress_tx);
+            for n in progress_rx {
+                progress.advance(n);
             }
 
-            cipher.process_chunk(&mut chunk);
+            workers
+                .into_iter()
+                .try_for_each(|worker| worker.join().expect("the worker should not panic"))
+        });
+
+        // Workers only know their own segments.
+        let result = match result {
+            Err(Error::Cancelled { .. }) => Err(Error::Cancelled {
+                bytes_processed: progress.bytes_processed,
+            }),
+            result => result,
+        };
+        progress.finish(result)
+    }
+}
+
+fn process_inner<I, O, C, R>(
+    input: I,
+    output: O,
+    cipher: C,
+    header: HeaderMode,
+    tag: TagMode,
+    progress: &mut Progress<R>,
+) -> Result<()>
+where
+    I: Read,
+    O: Write,
+    C: Cipher,
+    R: ProgressReporter,
+{
+    match tag {
+        TagMode::None => process_blocks(input, output, cipher, header, false, progress),
+        TagMode::Append(key) => {
+            let mut output = TagWriter::new(output, Mac::new(&key));
+            process_blocks(input, &mut output, cipher, header, true, progress)?;
+            output.finish()
+        }
+        TagMode::Verify(key) => {
+            let mut input = TagReader::new(input, Mac::new(&key));
+            process_blocks(&mut input, output, cipher, header, true, progress)?;
+            input.verify()
+        }
+    }
+}
+
+fn process_blocks<I, O, C, R>(
+    mut input: I,
+    mut output: O,
+    mut cipher: C,
+    header: HeaderMode,
+    tagged: bool,
+    progress: &mut Progress<R>,
+) -> Result<()>
+where
+    I: Read,
+    O: Write,
+    C: Cipher,
+    R: ProgressReporter,
+{
+    match header {
+        HeaderMode::None => {}
+        HeaderMode::Prepend(header) => tagged_header(header, tagged).write_to(&mut output)?,
+        HeaderMode::Strip => {
+            check_tag_flag(&Header::read_from(&mut input)?, tagged)?;
+            progress.advance(HEADER_LEN);
+        }
+    }
+
+    let mut block = vec![0; BLOCK_SIZE];
+    loop {
+        if let Err(err) = progress.check_cancelled() {
+            output.flush()?;
+            return Err(err);
+        }
+
+        // The block is only partially filled at EOF, so the partial
+        // chunk (if any) is always the tail of the stream.
+        let rd_len = read_full(&mut input, &mut block)?;
+        if rd_len == 0 {
+            break;
+        }
+
+        check_remaining_len(&cipher, rd_len as u64)?;
+        cipher.process_buffer(&mut block[0..rd_len]);
+
+        output.write_all(&block[0..rd_len])?;
+        progress.advance(rd_len);
+
+        if rd_len != BLOCK_SIZE {
+            break;
+        }
+    }
+
+    output.flush()?;
+    Ok(())
+}
+
+/// Asynchronous version of [`process_inner`].
+#[cfg(feature = "async")]
+async fn process_inner_async<I, O, C, R>(
+    input: I,
+    output: O,
+    cipher: C,
+    header: HeaderMode,
+    tag: TagMode,
+    progress: &mut Progress<R>,
+) -> Result<()>
+where
+    I: tokio::io::AsyncRead + Unpin,
+    O: tokio::io::AsyncWrite + Unpin,
+    C: Cipher,
+    R: ProgressReporter,
+{
+    match tag {
+        TagMode::None => process_blocks_async(input, output, cipher, header, false, progress).await,
+        TagMode::Append(key) => {
+            let mut output = TagWriter::new(output, Mac::new(&key));
+            process_blocks_async(input, &mut output, cipher, header, true, progress).await?;
+            output.finish_async().await
+        }
+        TagMode::Verify(key) => {
+            let mut input = TagReader::new(input, Mac::new(&key));
+            process_blocks_async(&mut input, output, cipher, header, true, progress).await?;
+            input.verify()
+        }
+    }
+}
+
+/// Asynchronous version of [`process_blocks`].
+#[cfg(feature = "async")]
+async fn process_blocks_async<I, O, C, R>(
+    mut input: I,
+    mut output: O,
+    mut cipher: C,
+    header: HeaderMode,
+    tagged: bool,
+    progress: &mut Progress<R>,
+) -> Result<()>
+where
+    I: tokio::io::AsyncRead + Unpin,
+    O: tokio::io::AsyncWrite + Unpin,
+    C: Cipher,
+    R: ProgressReporter,
+{
+    use tokio::io::AsyncWriteExt;
+
+    let mut block = vec![0; BLOCK_SIZE];
+    match header {
+        HeaderMode::None => {}
+        HeaderMode::Prepend(header) => {
+            let bytes = tagged_header(header, tagged).to_bytes();
+            output.write_all(&bytes).await?
+        }
+        HeaderMode::Strip => {
+            let rd_len = read_full_async(&mut input, &mut block[..HEADER_LEN]).await?;
+            check_tag_flag(&Header::read_from(&block[..rd_len])?, tagged)?;
+            progress.advance(HEADER_LEN);
+        }
+    }
+
+    loop {
+        if let Err(err) = progress.check_cancelled() {
+            output.flush().await?;
+            return Err(err);
+        }
+
+        let rd_len = read_full_async(&mut input, &mut block).await?;
+        if rd_len == 0 {
+            break;
+        }
+
+        check_remaining_len(&cipher, rd_len as u64)?;
+        cipher.process_buffer(&mut block[0..rd_len]);
+
+        output.write_all(&block[0..rd_len]).await?;
+        progress.advance(rd_len);
+
+        if rd_len != BLOCK_SIZE {
+            break;
+        }
+    }
+
+    output.flush().await?;
+    Ok(())
+}
+
+/// Processes the bytes of the file in `start..end` in place, recording
+/// each segment in the journal before overwriting it.
+///
+/// The journal is removed once all the bytes are processed.
+fn process_journaled<C, R>(
+    file: &mut File,
+    journal: &Path,
+    start: u64,
+    end: u64,
+    mut cipher: C,
+    progress: &mut Progress<R>,
+) -> Result<()>
+where
+    C: ResumableCipher,
+    R: ProgressReporter,
+{
+    let mut segment = vec![0; JOURNAL_SEGMENT_SIZE];
+    let mut offset = start;
+    while offset < end {
+        // The journal is kept, so a cancelled run can be resumed.
+        progress.check_cancelled()?;
+
+        let len = (end - offset).min(JOURNAL_SEGMENT_SIZE as u64) as usize;
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut segment[..len])?;
+        Journal::write(journal, offset, end, &cipher.snapshot(), &segment[..len])?;
+
+        cipher.process_buffer(&mut segment[..len]);
+
+        file.seek(SeekFrom::Start(offset))?;
+        file.write_all(&segment[..len])?;
+        file.sync_data()?;
+
+        offset += len as u64;
+        progress.advance(len);
+    }
+
+    match fs::remove_file(journal) {
+        Err(err) if err.kind() != IoErrorKind::NotFound => Err(err.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Returns the header to be written, marked if a tag follows the data.
+fn tagged_header(mut header: Header, tagged: bool) -> Header {
+    if tagged {
+        header.flags |= FLAG_TAGGED;
+    }
+    header
+}
+
+/// Checks whether the container carries a tag as expected.
+fn check_tag_flag(header: &Header, tagged: bool) -> Result<()> {
+    match (header.has_tag(), tagged) {
+        (true, false) => Err(Error::InvalidInput(
+            "the container has an integrity tag, which must be verified",
+        )),
+        (false, true) => Err(Error::InvalidFormat(
+            "the container has no integrity tag".into(),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Checks the integrity tag of the file at the given path without
+/// modifying it.
+fn verify_file_tag(path: &Path, key: &DataChunk, buffer_capacity: usize) -> Result<()> {
+    let file = BufReader::with_capacity(buffer_capacity, File::open(path)?);
+    let mut reader = TagReader::new(file, Mac::new(key));
+    io::copy(&mut reader, &mut io::sink())?;
+    reader.verify()
+}
+
+/// Fails with [`Error::Cancelled`] if the token is cancelled.
+fn check_cancelled(cancellation: Option<&CancellationToken>, bytes_processed: u64) -> Result<()> {
+    match cancellation {
+        Some(token) if token.is_cancelled() => Err(Error::Cancelled { bytes_processed }),
+        _ => Ok(()),
+    }
+}
+
+/// Writes the original bytes of the interrupted segment back to the file.
+fn restore_segment(file: &mut File, journal: &Journal) -> Result<()> {
+    file.seek(SeekFrom::Start(journal.offset))?;
+    file.write_all(&journal.original)?;
+    file.sync_data()?;
+    Ok(())
+}
+
+/// Returns the path of the temporary file used to write `dst`.
+///
+/// The file is placed in the same directory so that it can be renamed
+/// atomically.
+fn temp_path_for(dst: &Path) -> Result<PathBuf> {
+    let Some(file_name) = dst.file_name() else {
+        return Err(Error::InvalidInput("the destination is not a file path"));
+    };
+
+    let mut temp_name = OsString::from(".");
+    temp_name.push(file_name);
+    temp_name.push(format!(".{}.tmp", std::process::id()));
+    Ok(dst.with_file_name(temp_name))
+}
+
+/// Processes the bytes of the file in `start..end` in place.
+fn process_segment<C: Cipher>(
+    path: &Path,
+    start: u64,
+    end: u64,
+    mut cipher: C,
+    cancellation: Option<CancellationToken>,
+    progress_tx: Sender<usize>,
+) -> Result<()> {
+    let mut rd = File::open(path)?;
+    rd.seek(SeekFrom::Start(start))?;
+    let mut rd = rd.take(end - start);
+    let mut wr = File::options().write(true).open(path)?;
+    wr.seek(SeekFrom::Start(start))?;
 
-            output.write_all(&chunk.as_ref()[0..rd_len])?
+    let mut block = vec![0; BLOCK_SIZE];
+    let mut processed = 0;
+    loop {
+        check_cancelled(cancellation.as_ref(), processed)?;
+
+        let rd_len = read_full(&mut rd, &mut block)?;
+        if rd_len == 0 {
+            return Ok(());
         }
+
+        cipher.process_buffer(&mut block[0..rd_len]);
+
+        wr.write_all(&block[0..rd_len])?;
+
+        // The receiver only goes away after all workers exit.
+        let _ = progress_tx.send(rd_len);
+        processed += rd_len as u64;
     }
 }
 
@@ -174,8 +1133,575 @@ where
 {
     fn clone(&self) -> Self {
         Self {
-            buffered: self.buffered,
+            read_buffer_capacity: self.read_buffer_capacity,
+            write_buffer_capacity: self.write_buffer_capacity,
+            header: self.header,
+            tag: self.tag,
+            workers: self.workers,
+            cancellation: self.cancellation.clone(),
             progress_reporter: self.progress_reporter.clone(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::assert_matches::assert_matches;
+    use std::fs;
+    use std::io::{Cursor, Read, Result};
+    use std::path::Path;
+
+    use super::{
+        CancellationToken, Pipeline, ProgressReporter, Summary, DEFAULT_BUFFER_CAPACITY,
+        JOURNAL_SEGMENT_SIZE,
+    };
+    use crate::error::Error;
+    use crate::header::{Header, HEADER_LEN};
+    use crate::integrity::TAG_LEN;
+    use crate::journal::{journal_path, Journal};
+    use crate::key_init::KeyDerivation;
+    use crate::test_utils::{
+        encrypt_with_pipeline, payload, payload_with_len, temp_file_path, SEED_PHRASE,
+    };
+    use crate::types::DataChunk;
+    use crate::{
+        Cipher, DecryptState, EncryptState, KeyInit, ResumableCipher, SeekableDecryptState,
+        SeekableEncryptState,
+    };
+
+    /// A reader that returns randomly sized short reads.
+    struct ShortReader<R> {
+        inner: R,
+        rng_state: u32,
+    }
+
+    impl<R> ShortReader<R> {
+        fn new(inner: R, seed: u32) -> Self {
+            Self {
+                inner,
+                rng_state: seed | 1,
+            }
+        }
+
+        fn next_len(&mut self) -> usize {
+            // Xorshift32, good enough for shuffling read sizes.
+            let mut x = self.rng_state;
+            x ^= x << 13;
+            x ^= x >> 17;
+            x ^= x << 5;
+            self.rng_state = x;
+            (x % 13) as usize + 1
+        }
+    }
+
+    impl<R: Read> Read for ShortReader<R> {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+            let len = self.next_len().min(buf.len());
+            self.inner.read(&mut buf[..len])
+        }
+    }
+
+    /// A reporter that records the events it receives.
+    #[derive(Default)]
+    struct Recorder {
+        total: Option<u64>,
+        bytes_processed: u64,
+        events: Vec<&'static str>,
+        summary: Option<Summary>,
+    }
+
+    impl ProgressReporter for Recorder {
+        fn started(&mut self, total: Option<u64>) {
+            self.total = total;
+            self.events.push("started");
+        }
+
+        fn bytes_processed(&mut self, n: u64) {
+            self.bytes_processed += n;
+        }
+
+        fn finished(&mut self, summary: &Summary) {
+            self.events.push("finished");
+            self.summary = Some(*summary);
+        }
+
+        fn failed(&mut self, _error: &Error, summary: &Summary) {
+            self.events.push("failed");
+            self.summary = Some(*summary);
+        }
+
+        fn cancelled(&mut self, summary: &Summary) {
+            self.events.push("cancelled");
+            self.summary = Some(*summary);
+        }
+    }
+
+    /// A reporter that cancels the token once some bytes are processed.
+    struct CancelAfter(CancellationToken);
+
+    impl ProgressReporter for CancelAfter {
+        fn bytes_processed(&mut self, _n: u64) {
+            self.0.cancel();
+        }
+    }
+
+    #[test]
+    fn short_reads_do_not_affect_output() {
+        let plain = payload();
+        let expected = encrypt_with_pipeline(Cursor::new(&plain));
+
+        for seed in 1..32 {
+            let output = encrypt_with_pipeline(ShortReader::new(Cursor::new(&plain), seed));
+            assert_eq!(output, expected, "mismatched output with seed {}", seed);
+
+            let buffered_output = {
+                let mut output = vec![];
+                Pipeline::new()
+                    .with_buffer()
+                    .process(
+                        ShortReader::new(Cursor::new(&plain), seed),
+                        &mut output,
+                        EncryptState::with_seed_phrase(SEED_PHRASE),
+                        None,
+                    )
+                    .expect("failed to encrypt");
+                output
+            };
+            assert_eq!(buffered_output, expected);
+
+            let small_buffered_output = {
+                let mut output = vec![];
+                Pipeline::new()
+                    .with_read_buffer_capacity(13)
+                    .with_write_buffer_capacity(5)
+                    .process(
+                        ShortReader::new(Cursor::new(&plain), seed),
+                        &mut output,
+                        EncryptState::with_seed_phrase(SEED_PHRASE),
+                        None,
+                    )
+                    .expect("failed to encrypt");
+                output
+            };
+            assert_eq!(small_buffered_output, expected);
+        }
+    }
+
+    #[test]
+    fn progress_events() {
+        let plain = payload();
+        let mut recorder = Recorder::default();
+        Pipeline::new()
+            .with_progress_reporter(&mut recorder)
+            .process(
+                Cursor::new(&plain),
+                &mut vec![],
+                EncryptState::with_seed_phrase(SEED_PHRASE),
+                Some(plain.len() as u64),
+            )
+            .expect("failed to encrypt");
+        assert_eq!(recorder.events, ["started", "finished"]);
+        assert_eq!(recorder.total, Some(plain.len() as u64));
+        assert_eq!(recorder.bytes_processed, plain.len() as u64);
+        let summary = recorder.summary.expect("no summary");
+        assert_eq!(summary.bytes_processed, plain.len() as u64);
+
+        let mut recorder = Recorder::default();
+        let result = Pipeline::new()
+            .strip_header()
+            .with_progress_reporter(&mut recorder)
+            .process(
+                Cursor::new(&plain),
+                &mut vec![],
+                DecryptState::with_seed_phrase(SEED_PHRASE),
+                None,
+            );
+        assert_matches!(result, Err(Error::InvalidFormat(_)));
+        assert_eq!(recorder.events, ["started", "failed"]);
+        assert_eq!(recorder.summary.map(|s| s.bytes_processed), Some(0));
+    }
+
+    #[test]
+    fn cancellation() {
+        let plain = payload_with_len(JOURNAL_SEGMENT_SIZE + 5001);
+        let key = DataChunk::with_seed_phrase(SEED_PHRASE);
+
+        let token = CancellationToken::new();
+        token.cancel();
+        let mut recorder = Recorder::default();
+        let result = Pipeline::new()
+            .with_cancellation_token(token)
+            .with_progress_reporter(&mut recorder)
+            .process(
+                Cursor::new(&plain),
+                &mut vec![],
+                EncryptState::with_key(key),
+                None,
+            );
+        assert_matches!(result, Err(Error::Cancelled { bytes_processed: 0 }));
+        assert_eq!(recorder.events, ["started", "cancelled"]);
+
+        let path = temp_file_path("cancellation");
+        fs::write(&path, &plain).expect("failed to write file");
+        let token = CancellationToken::new();
+        let result = Pipeline::new()
+            .with_buffer()
+            .with_cancellation_token(token.clone())
+            .with_progress_reporter(CancelAfter(token))
+            .process_file(&path, EncryptState::with_key(key));
+        let Err(Error::Cancelled { bytes_processed }) = result else {
+            panic!("unexpected result: {:?}", result);
+        };
+        let processed = bytes_processed as usize;
+        assert!(processed > 0 && processed < plain.len());
+        let mut expected = plain.clone();
+        EncryptState::with_key(key).process_buffer(&mut expected[..processed]);
+        assert!(fs::read(&path).expect("failed to read file") == expected);
+
+        // A cancelled journaled run can be resumed.
+        fs::write(&path, &plain).expect("failed to write file");
+        let token = CancellationToken::new();
+        let result = Pipeline::new()
+            .with_cancellation_token(token.clone())
+            .with_progress_reporter(CancelAfter(token))
+            .process_file_journaled(&path, EncryptState::with_key(key));
+        assert_matches!(result, Err(Error::Cancelled { .. }));
+        Pipeline::new()
+            .resume_file(&path, EncryptState::with_key(key))
+            .expect("failed to resume");
+        let resumed = fs::read(&path).expect("failed to read file");
+        fs::remove_file(&path).expect("failed to remove file");
+        let mut expected = plain.clone();
+        EncryptState::with_key(key).process_buffer(&mut expected);
+        assert!(resumed == expected);
+    }
+
+    #[test]
+    fn decrypt_through_short_reads() {
+        let plain = payload();
+        let cipher_text = encrypt_with_pipeline(ShortReader::new(Cursor::new(&plain), 42));
+
+        for seed in 1..32 {
+            let mut decrypted = vec![];
+            Pipeline::new()
+                .process(
+                    ShortReader::new(Cursor::new(&cipher_text), seed),
+                    &mut decrypted,
+                    DecryptState::with_seed_phrase(SEED_PHRASE),
+                    None,
+                )
+                .expect("failed to decrypt");
+            assert_eq!(decrypted, plain, "mismatched output with seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn header_round_trip() {
+        let plain = payload();
+        let key = DataChunk::with_seed_phrase(SEED_PHRASE);
+
+        let mut container = vec![];
+        Pipeline::new()
+            .with_header(Header::new(&key, KeyDerivation::V1))
+            .process(
+                Cursor::new(&plain),
+                &mut container,
+                EncryptState::with_key(key),
+                None,
+            )
+            .expect("failed to encrypt");
+        assert_eq!(container.len(), plain.len() + HEADER_LEN);
+        assert_eq!(
+            &container[HEADER_LEN..],
+            encrypt_with_pipeline(Cursor::new(&plain))
+        );
+
+        let mut decrypted = vec![];
+        Pipeline::new()
+            .strip_header()
+            .process(
+                Cursor::new(&container),
+                &mut decrypted,
+                DecryptState::with_key(key),
+                None,
+            )
+            .expect("failed to decrypt");
+        assert_eq!(decrypted, plain);
+
+        let result = Pipeline::new().strip_header().process(
+            Cursor::new(&plain),
+            &mut vec![],
+            DecryptState::with_key(key),
+            None,
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn tag_round_trip() {
+        let plain = payload();
+        let key = DataChunk::with_seed_phrase(SEED_PHRASE);
+
+        let mut container = vec![];
+        Pipeline::new()
+            .with_header(Header::new(&key, KeyDerivation::V1))
+            .with_tag(&key)
+            .process(
+                Cursor::new(&plain),
+                &mut container,
+                EncryptState::with_key(key),
+                None,
+            )
+            .expect("failed to encrypt");
+        assert_eq!(container.len(), HEADER_LEN + plain.len() + TAG_LEN);
+        let header = Header::read_from(Cursor::new(&container)).expect("invalid header");
+        assert!(header.has_tag());
+
+        let decrypt = |container: &[u8]| {
+            let mut decrypted = vec![];
+            Pipeline::new()
+                .strip_header()
+                .verify_tag(&key)
+                .process(
+                    ShortReader::new(Cursor::new(container), 7),
+                    &mut decrypted,
+                    DecryptState::with_key(key),
+                    None,
+                )
+                .map(|()| decrypted)
+        };
+        assert_eq!(decrypt(&container).expect("failed to decrypt"), plain);
+
+        let mut tampered = container.clone();
+        tampered[HEADER_LEN + 100] ^= 0x10;
+        assert_matches!(decrypt(&tampered), Err(Error::TagMismatch));
+        assert_matches!(
+            decrypt(&container[..container.len() - 1]),
+            Err(Error::TagMismatch)
+        );
+
+        let result = Pipeline::new().strip_header().process(
+            Cursor::new(&container),
+            &mut vec![],
+            DecryptState::with_key(key),
+            None,
+        );
+        assert_matches!(result, Err(Error::InvalidInput(_)));
+    }
+
+    #[test]
+    fn process_file_with_tag() {
+        let plain = payload();
+        let key = DataChunk::with_seed_phrase(SEED_PHRASE);
+        let path = temp_file_path("process_file_with_tag");
+        fs::write(&path, &plain).expect("failed to write file");
+
+        Pipeline::new()
+            .with_header(Header::new(&key, KeyDerivation::V1))
+            .with_tag(&key)
+            .process_file(&path, EncryptState::with_key(key))
+            .expect("failed to encrypt");
+        let container = fs::read(&path).expect("failed to read file");
+        assert_eq!(container.len(), HEADER_LEN + plain.len() + TAG_LEN);
+
+        // A tampered file is left untouched.
+        let mut tampered = container.clone();
+        tampered[HEADER_LEN + 100] ^= 0x10;
+        fs::write(&path, &tampered).expect("failed to write file");
+        let result = Pipeline::new()
+            .strip_header()
+            .verify_tag(&key)
+            .process_file(&path, DecryptState::with_key(key));
+        assert_matches!(result, Err(Error::TagMismatch));
+        assert!(fs::read(&path).expect("failed to read file") == tampered);
+
+        fs::write(&path, &container).expect("failed to write file");
+        let mut recorder = Recorder::default();
+        Pipeline::new()
+            .strip_header()
+            .verify_tag(&key)
+            .with_progress_reporter(&mut recorder)
+            .process_file(&path, DecryptState::with_key(key))
+            .expect("failed to decrypt");
+        // The progress reaches the total, which excludes the tag.
+        let total_len = (container.len() - TAG_LEN) as u64;
+        assert_eq!(recorder.total, Some(total_len));
+        assert_eq!(recorder.bytes_processed, total_len);
+        let decrypted = fs::read(&path).expect("failed to read file");
+        fs::remove_file(&path).expect("failed to remove file");
+        assert_eq!(decrypted, plain);
+    }
+
+    #[test]
+    fn process_file_with_header() {
+        let plain = payload();
+        let key = DataChunk::with_seed_phrase(SEED_PHRASE);
+        let path = temp_file_path("process_file_with_header");
+
+        // The header is longer than the smaller write buffers.
+        for capacity in [0, 5, DEFAULT_BUFFER_CAPACITY] {
+            fs::write(&path, &plain).expect("failed to write file");
+            Pipeline::new()
+                .with_buffer_capacity(capacity)
+                .with_header(Header::new(&key, KeyDerivation::V1))
+                .process_file(&path, EncryptState::with_key(key))
+                .expect("failed to encrypt");
+            let container = fs::read(&path).expect("failed to read file");
+            assert_eq!(container.len(), plain.len() + HEADER_LEN);
+            let header = Header::read_from(Cursor::new(&container)).expect("invalid header");
+            assert!(header.matches_key(&key));
+            assert_eq!(
+                &container[HEADER_LEN..],
+                encrypt_with_pipeline(Cursor::new(&plain))
+            );
+
+            Pipeline::new()
+                .with_buffer_capacity(capacity)
+                .strip_header()
+                .process_file(&path, DecryptState::with_key(key))
+                .expect("failed to decrypt");
+            let decrypted = fs::read(&path).expect("failed to read file");
+            assert_eq!(decrypted, plain);
+        }
+        fs::remove_file(&path).expect("failed to remove file");
+    }
+
+    #[test]
+    fn process_file_to() {
+        let plain = payload();
+        let key = DataChunk::with_seed_phrase(SEED_PHRASE);
+        let src = temp_file_path("process_file_to.src");
+        let dst = temp_file_path("process_file_to.dst");
+        fs::write(&src, &plain).expect("failed to write file");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&src, fs::Permissions::from_mode(0o640))
+                .expect("failed to set permissions");
+        }
+
+        Pipeline::new()
+            .with_header(Header::new(&key, KeyDerivation::V1))
+            .process_file_to(&src, &dst, EncryptState::with_key(key))
+            .expect("failed to encrypt");
+        assert_eq!(fs::read(&src).expect("failed to read file"), plain);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&dst)
+                .expect("failed to read metadata")
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o640);
+        }
+        let container = fs::read(&dst).expect("failed to read file");
+        assert_eq!(
+            &container[HEADER_LEN..],
+            encrypt_with_pipeline(Cursor::new(&plain))
+        );
+
+        // A failure leaves the destination as it was.
+        let result =
+            Pipeline::new()
+                .strip_header()
+                .process_file_to(&src, &dst, DecryptState::with_key(key));
+        assert!(result.is_err());
+        assert_eq!(fs::read(&dst).expect("failed to read file"), container);
+        let result = Pipeline::new().strip_header().process_to_file(
+            Cursor::new(&container[..HEADER_LEN - 1]),
+            &dst,
+            DecryptState::with_key(key),
+            None,
+        );
+        assert!(result.is_err());
+        assert_eq!(fs::read(&dst).expect("failed to read file"), container);
+
+        Pipeline::new()
+            .strip_header()
+            .process_file_to(&dst, &src, DecryptState::with_key(key))
+            .expect("failed to decrypt");
+        let decrypted = fs::read(&src).expect("failed to read file");
+        fs::remove_file(&src).expect("failed to remove file");
+        fs::remove_file(&dst).expect("failed to remove file");
+        assert_eq!(decrypted, plain);
+    }
+
+    /// Leaves the file as if the journaled encryption was killed in the
+    /// middle of the second segment.
+    fn interrupt_encryption(path: &Path, plain: &[u8], key: DataChunk) {
+        let mut data = plain.to_vec();
+        let mut cipher = EncryptState::with_key(key);
+        let (head, rest) = data.split_at_mut(JOURNAL_SEGMENT_SIZE);
+        cipher.process_buffer(head);
+        let journal = journal_path(path);
+        let original = &plain[JOURNAL_SEGMENT_SIZE..JOURNAL_SEGMENT_SIZE * 2];
+        Journal::write(
+            &journal,
+            JOURNAL_SEGMENT_SIZE as u64,
+            plain.len() as u64,
+            &cipher.snapshot(),
+            original,
+        )
+        .expect("failed to write journal");
+        rest[..1000].fill(0xaa);
+        fs::write(path, &data).expect("failed to write file");
+    }
+
+    #[test]
+    fn process_file_journaled() {
+        let plain = payload_with_len(JOURNAL_SEGMENT_SIZE * 2 + 1001);
+        let key = DataChunk::with_seed_phrase(SEED_PHRASE);
+        let path = temp_file_path("process_file_journaled");
+        let mut expected = plain.clone();
+        EncryptState::with_key(key).process_buffer(&mut expected);
+
+        fs::write(&path, &plain).expect("failed to write file");
+        Pipeline::new()
+            .process_file_journaled(&path, EncryptState::with_key(key))
+            .expect("failed to encrypt");
+        assert!(fs::read(&path).expect("failed to read file") == expected);
+        assert!(!journal_path(&path).exists());
+
+        interrupt_encryption(&path, &plain, key);
+        let result = Pipeline::new().process_file_journaled(&path, EncryptState::with_key(key));
+        assert!(result.is_err());
+        Pipeline::new()
+            .resume_file(&path, EncryptState::with_key(key))
+            .expect("failed to resume");
+        assert!(fs::read(&path).expect("failed to read file") == expected);
+        assert!(!journal_path(&path).exists());
+
+        interrupt_encryption(&path, &plain, key);
+        Pipeline::new()
+            .rollback_file(&path, DecryptState::with_key(key))
+            .expect("failed to roll back");
+        let rolled_back = fs::read(&path).expect("failed to read file");
+        fs::remove_file(&path).expect("failed to remove file");
+        assert!(rolled_back == plain);
+        assert!(!journal_path(&path).exists());
+    }
+
+    #[test]
+    fn process_file_parallel() {
+        let plain = payload_with_len(300_005);
+        let key = DataChunk::with_seed_phrase(SEED_PHRASE);
+        let path = temp_file_path("process_file_parallel");
+        fs::write(&path, &plain).expect("failed to write file");
+
+        Pipeline::new()
+            .with_workers(4)
+            .process_file_parallel(&path, SeekableEncryptState::with_key(key))
+            .expect("failed to encrypt");
+        let mut expected = plain.clone();
+        SeekableEncryptState::with_key(key).process_buffer(&mut expected);
+        assert!(fs::read(&path).expect("failed to read file") == expected);
+
+        Pipeline::new()
+            .with_workers(3)
+            .process_file_parallel(&path, SeekableDecryptState::with_key(key))
+            .expect("failed to decrypt");
+        let decrypted = fs::read(&path).expect("failed to read file");
+        fs::remove_file(&path).expect("failed to remove file");
+        assert!(decrypted == plain);
+    }
+}
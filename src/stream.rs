@@ -0,0 +1,374 @@
+//! Streaming adapters over [`Read`] and [`Write`].
+//!
+//! [`CipherReader`] and [`CipherWriter`] wrap any reader or writer with a
+//! [`Cipher`], so that yafo can be slotted into existing I/O stacks without
+//! an intermediate buffer. Both adapters split the data into chunks exactly
+//! like [`Pipeline`] does, thus the output is identical regardless of how
+//! the data is fed.
+//!
+//! ## Example
+//!
+//! ```rust
+//! # use std::io::{Read, Write};
+//! # use yafo::stream::{CipherReader, CipherWriter};
+//! # use yafo::{DecryptState, EncryptState, KeyInit};
+//! let seed_phrase = "you can not see me";
+//!
+//! let mut writer = CipherWriter::new(vec![], EncryptState::with_seed_phrase(seed_phrase));
+//! writer.write_all(b"hello").unwrap();
+//! let cipher_text = writer.finish().unwrap();
+//!
+//! let mut reader = CipherReader::new(&cipher_text[..], DecryptState::with_seed_phrase(seed_phrase));
+//! let mut plain_text = String::new();
+//! reader.read_to_string(&mut plain_text).unwrap();
+//! assert_eq!(plain_text, "hello");
+//! ```
+//!
+//! With a [`SeekableCipher`], [`CipherReader`] also implements [`Seek`]
+//! if the inner reader does, which allows serving ranges of the data
+//! without processing everything before them.
+//!
+//! [`Pipeline`]: crate::pipeline::Pipeline
+
+use std::io::{Error as IoError, ErrorKind as IoErrorKind, Read, Result, Seek, SeekFrom, Write};
+
+use crate::cipher::SeekableCipher;
+use crate::io::chunks::{ChunkAssembler, Step};
+use crate::io::read_full;
+use crate::types::{DataChunk, CHUNK_SIZE};
+use crate::Cipher;
+
+const SCRATCH_SIZE: usize = 4096;
+
+/// A reader that processes the bytes read from the inner reader.
+pub struct CipherReader<R, C> {
+    inner: R,
+    cipher: C,
+    chunks: ChunkAssembler,
+    /// Number of bytes consumed from this reader.
+    position: u64,
+}
+
+impl<R, C> CipherReader<R, C> {
+    /// Creates a new reader that processes the bytes from `inner` with
+    /// the given cipher.
+    pub fn new(inner: R, cipher: C) -> Self {
+        Self {
+            inner,
+            cipher,
+            chunks: ChunkAssembler::new(),
+            position: 0,
+        }
+    }
+
+    /// Gets a reference to the inner reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Gets a mutable reference to the inner reader.
+    ///
+    /// Reading directly from the inner reader will corrupt the output.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Unwraps this reader, returning the inner reader.
+    ///
+    /// Bytes that are read but not yet consumed are lost.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read, C: Cipher> Read for CipherReader<R, C> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let rd_len = self.read_chunks(buf)?;
+        self.position += rd_len as u64;
+        Ok(rd_len)
+    }
+}
+
+impl<R: Read, C: Cipher> CipherReader<R, C> {
+    fn read_chunks(&mut self, buf: &mut [u8]) -> Result<usize> {
+        loop {
+            match self.chunks.next_step(buf) {
+                Step::Ready(len) => return Ok(len),
+                Step::ReadDirect => {
+                    let rd_len = match self.inner.read(buf) {
+                        Ok(rd_len) => rd_len,
+                        Err(err) if err.kind() == IoErrorKind::Interrupted => continue,
+                        Err(err) => return Err(err),
+                    };
+                    if let Some(len) = self.chunks.direct_read(&mut self.cipher, buf, rd_len) {
+                        return Ok(len);
+                    }
+                }
+                Step::ReadChunk => {
+                    let rd_len = match self.inner.read(self.chunks.unfilled()) {
+                        Ok(rd_len) => rd_len,
+                        Err(err) if err.kind() == IoErrorKind::Interrupted => continue,
+                        Err(err) => return Err(err),
+                    };
+                    self.chunks.chunk_read(&mut self.cipher, rd_len);
+                }
+            }
+        }
+    }
+}
+
+impl<R: Read + Seek, C: SeekableCipher> Seek for CipherReader<R, C> {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => offset,
+            SeekFrom::End(_) => self.inner.seek(pos)?,
+            SeekFrom::Current(offset) => {
+                self.position.checked_add_signed(offset).ok_or_else(|| {
+                    IoError::new(
+                        IoErrorKind::InvalidInput,
+                        "invalid seek to a negative or overflowing position",
+                    )
+                })?
+            }
+        };
+
+        // Start over from the chunk containing the target position.
+        let chunk_index = target / CHUNK_SIZE as u64;
+        self.inner
+            .seek(SeekFrom::Start(chunk_index * CHUNK_SIZE as u64))?;
+        self.cipher.seek_chunk(chunk_index)?;
+        self.chunks.reset();
+
+        let mut skipped = [0; CHUNK_SIZE];
+        read_full(self, &mut skipped[..(target % CHUNK_SIZE as u64) as usize])?;
+        self.position = target;
+        Ok(target)
+    }
+}
+
+/// A writer that processes the bytes before writing them to the inner
+/// writer.
+///
+/// Bytes that don't form a whole chunk are kept in the writer until more
+/// bytes come, so [`Write::flush`] won't write them out. The last partial
+/// chunk is processed by [`CipherWriter::finish`], or when the writer is
+/// dropped, in which case errors are ignored.
+pub struct CipherWriter<W: Write, C: Cipher> {
+    inner: Option<W>,
+    cipher: C,
+    chunk: DataChunk,
+    filled: usize,
+}
+
+impl<W: Write, C: Cipher> CipherWriter<W, C> {
+    /// Creates a new writer that processes the bytes with the given cipher
+    /// and writes them to `inner`.
+    pub fn new(inner: W, cipher: C) -> Self {
+        Self {
+            inner: Some(inner),
+            cipher,
+            chunk: DataChunk::default(),
+            filled: 0,
+        }
+    }
+
+    /// Gets a reference to the inner writer.
+    pub fn get_ref(&self) -> &W {
+        self.inner.as_ref().expect("the writer is not finished")
+    }
+
+    /// Gets a mutable reference to the inner writer.
+    ///
+    /// Writing directly to the inner writer will corrupt the output.
+    pub fn get_mut(&mut self) -> &mut W {
+        unfinished(&mut self.inner)
+    }
+
+    /// Processes the last partial chunk, flushes and returns the inner
+    /// writer.
+    pub fn finish(mut self) -> Result<W> {
+        self.write_tail()?;
+        let mut inner = self.inner.take().expect("the writer is not finished");
+        inner.flush()?;
+        Ok(inner)
+    }
+
+    fn write_tail(&mut self) -> Result<()> {
+        if self.filled == 0 {
+            return Ok(());
+        }
+
+        let len = self.filled;
+        self.filled = 0;
+        self.cipher
+            .process_buffer(&mut self.chunk.as_mut_slice()[..len]);
+        unfinished(&mut self.inner).write_all(&self.chunk.as_ref()[..len])
+    }
+}
+
+impl<W: Write, C: Cipher> Write for CipherWriter<W, C> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let mut consumed = 0;
+
+        if self.filled > 0 {
+            let len = (CHUNK_SIZE - self.filled).min(buf.len());
+            self.chunk.as_mut_slice()[self.filled..self.filled + len].copy_from_slice(&buf[..len]);
+            self.filled += len;
+            consumed += len;
+
+            if self.filled < CHUNK_SIZE {
+                return Ok(consumed);
+            }
+            self.filled = 0;
+            self.cipher.process_chunk(&mut self.chunk);
+            unfinished(&mut self.inner).write_all(self.chunk.as_ref())?;
+        }
+
+        let mut scratch = [0; SCRATCH_SIZE];
+        while buf.len() - consumed >= CHUNK_SIZE {
+            let len = (buf.len() - consumed).min(SCRATCH_SIZE);
+            let len = len - len % CHUNK_SIZE;
+            scratch[..len].copy_from_slice(&buf[consumed..consumed + len]);
+            self.cipher.process_buffer(&mut scratch[..len]);
+            unfinished(&mut self.inner).write_all(&scratch[..len])?;
+            consumed += len;
+        }
+
+        let len = buf.len() - consumed;
+        self.chunk.as_mut_slice()[..len].copy_from_slice(&buf[consumed..]);
+        self.filled = len;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        unfinished(&mut self.inner).flush()
+    }
+}
+
+impl<W: Write, C: Cipher> Drop for CipherWriter<W, C> {
+    fn drop(&mut self) {
+        if self.inner.is_some() {
+            // Dropping is not the right place to report errors, just like
+            // `BufWriter`.
+            let _ = self.write_tail();
+        }
+    }
+}
+
+/// The inner writer is only taken by [`CipherWriter::finish`], which
+/// consumes the writer.
+fn unfinished<W>(inner: &mut Option<W>) -> &mut W {
+    inner.as_mut().expect("the writer is not finished")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+
+    use super::{CipherReader, CipherWriter};
+    use crate::test_utils::{encrypt_with_pipeline, payload_with_len, SEED_PHRASE};
+    use crate::{
+        Cipher, DecryptState, EncryptState, KeyInit, SeekableDecryptState, SeekableEncryptState,
+    };
+
+    const PAYLOAD_LEN: usize = 9013;
+    const IO_SIZES: [usize; 7] = [1, 3, 8, 13, 64, 5000, 10000];
+
+    #[test]
+    fn reader_matches_pipeline() {
+        let plain = payload_with_len(PAYLOAD_LEN);
+        let expected = encrypt_with_pipeline(plain.as_slice());
+
+        for size in IO_SIZES {
+            let mut reader =
+                CipherReader::new(&plain[..], EncryptState::with_seed_phrase(SEED_PHRASE));
+            let mut output = vec![];
+            let mut buf = vec![0; size];
+            loop {
+                let rd_len = reader.read(&mut buf).expect("failed to read");
+                if rd_len == 0 {
+                    break;
+                }
+                output.extend_from_slice(&buf[..rd_len]);
+            }
+            assert_eq!(output, expected, "mismatched output with size {}", size);
+        }
+    }
+
+    #[test]
+    fn writer_matches_pipeline() {
+        let plain = payload_with_len(PAYLOAD_LEN);
+        let expected = encrypt_with_pipeline(plain.as_slice());
+
+        for size in IO_SIZES {
+            let mut writer = CipherWriter::new(vec![], EncryptState::with_seed_phrase(SEED_PHRASE));
+            for bytes in plain.chunks(size) {
+                writer.write_all(bytes).expect("failed to write");
+                writer.flush().expect("failed to flush");
+            }
+            let output = writer.finish().expect("failed to finish");
+            assert_eq!(output, expected, "mismatched output with size {}", size);
+        }
+    }
+
+    #[test]
+    fn writer_processes_tail_on_drop() {
+        let plain = payload_with_len(PAYLOAD_LEN);
+        let mut output = vec![];
+        {
+            let mut writer =
+                CipherWriter::new(&mut output, EncryptState::with_seed_phrase(SEED_PHRASE));
+            writer.write_all(&plain).expect("failed to write");
+        }
+        assert_eq!(output, encrypt_with_pipeline(plain.as_slice()));
+    }
+
+    #[test]
+    fn round_trip() {
+        let plain = payload_with_len(PAYLOAD_LEN);
+        let mut writer = CipherWriter::new(vec![], EncryptState::with_seed_phrase(SEED_PHRASE));
+        writer.write_all(&plain).expect("failed to write");
+        let cipher_text = writer.finish().expect("failed to finish");
+
+        let mut reader = CipherReader::new(
+            &cipher_text[..],
+            DecryptState::with_seed_phrase(SEED_PHRASE),
+        );
+        let mut decrypted = vec![];
+        reader.read_to_end(&mut decrypted).expect("failed to read");
+        assert_eq!(decrypted, plain);
+    }
+
+    #[test]
+    fn seek_reader() {
+        let plain = payload_with_len(PAYLOAD_LEN);
+        let mut cipher_text = plain.clone();
+        SeekableEncryptState::with_seed_phrase(SEED_PHRASE).process_buffer(&mut cipher_text);
+
+        let mut reader = CipherReader::new(
+            Cursor::new(&cipher_text),
+            SeekableDecryptState::with_seed_phrase(SEED_PHRASE),
+        );
+        let mut buf = [0; 100];
+        for offset in [0, 1, 8, 13, 4999, 9000] {
+            assert_eq!(reader.seek(SeekFrom::Start(offset)).unwrap(), offset);
+            let rd_len = reader.read(&mut buf).expect("failed to read");
+            let offset = offset as usize;
+            assert_eq!(buf[..rd_len], plain[offset..offset + rd_len]);
+        }
+
+        reader.seek(SeekFrom::Start(100)).unwrap();
+        reader.read_exact(&mut buf[..10]).expect("failed to read");
+        assert_eq!(reader.seek(SeekFrom::Current(-50)).unwrap(), 60);
+        let mut tail = vec![];
+        reader.read_to_end(&mut tail).expect("failed to read");
+        assert_eq!(tail, plain[60..]);
+
+        assert_eq!(reader.seek(SeekFrom::End(-3)).unwrap(), 9010);
+        tail.clear();
+        reader.read_to_end(&mut tail).expect("failed to read");
+        assert_eq!(tail, plain[9010..]);
+
+        assert!(reader.seek(SeekFrom::Current(-10000)).is_err());
+    }
+}
@@ -0,0 +1,38 @@
+//! Fixtures shared by the unit tests.
+
+use std::io::Read;
+use std::path::PathBuf;
+
+use crate::{EncryptState, KeyInit, Pipeline};
+
+pub const SEED_PHRASE: &str = "you can not see me";
+
+/// Returns a payload that is not a multiple of the chunk size.
+pub fn payload() -> Vec<u8> {
+    payload_with_len(1021)
+}
+
+/// Returns a payload of the given length without short periods.
+pub fn payload_with_len(len: usize) -> Vec<u8> {
+    (0..len as u32).map(|i| (i * 7 + i / 3) as u8).collect()
+}
+
+/// Encrypts the input with an unbuffered pipeline and [`SEED_PHRASE`].
+pub fn encrypt_with_pipeline<I: Read>(input: I) -> Vec<u8> {
+    let mut output = vec![];
+    Pipeline::new()
+        .process(
+            input,
+            &mut output,
+            EncryptState::with_seed_phrase(SEED_PHRASE),
+            None,
+        )
+        .expect("failed to encrypt");
+    output
+}
+
+/// Returns a path in the temporary directory that is unique to the
+/// test process.
+pub fn temp_file_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("yafo-test-{}-{}", std::process::id(), name))
+}
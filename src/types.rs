@@ -8,6 +8,7 @@ pub const CHUNK_SIZE: usize = 8;
 
 /// Struct representing a fixed-length data chunk.
 #[derive(Default, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DataChunk(pub [u8; CHUNK_SIZE]);
 
 impl DataChunk {
@@ -43,15 +44,15 @@ impl<'a> IntoIterator for &'a DataChunk {
     }
 }
 
-impl<'a> Index<usize> for DataChunk {
+impl Index<usize> for DataChunk {
     type Output = u8;
 
     fn index(&self, index: usize) -> &Self::Output {
-        &self.0.index(index)
+        self.0.index(index)
     }
 }
 
-impl<'a> IndexMut<usize> for DataChunk {
+impl IndexMut<usize> for DataChunk {
     fn index_mut(&mut self, index: usize) -> &mut Self::Output {
         self.0.index_mut(index)
     }